@@ -0,0 +1,131 @@
+use crate::settings::Settings;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+// limits in bytes per second from the settings, 0 for no limit
+static TOTAL_RATE: AtomicU64 = AtomicU64::new(0);
+static DOWNLOAD_RATE: AtomicU64 = AtomicU64::new(0);
+
+// shared by all running transfers
+static TOTAL: Mutex<TokenBucket> = Mutex::new(TokenBucket::new(0));
+
+/// Token bucket holding at most one second worth of bytes
+#[derive(Debug)]
+pub struct TokenBucket {
+    /// bytes per second, 0 for no limit
+    rate: u64,
+    tokens: f64,
+    updated: Option<Instant>,
+}
+
+impl TokenBucket {
+    pub const fn new(rate: u64) -> Self {
+        TokenBucket {
+            rate,
+            tokens: rate as f64,
+            updated: None,
+        }
+    }
+
+    pub fn set_rate(&mut self, rate: u64) {
+        if rate != self.rate {
+            *self = TokenBucket {
+                updated: self.updated,
+                ..TokenBucket::new(rate)
+            };
+        }
+    }
+
+    /// Takes `amount` bytes at `now` and returns how long to wait before
+    /// sending them. Chunks larger than the bucket put it into debt, which
+    /// the following chunks have to wait for.
+    pub fn take(&mut self, amount: u64, now: Instant) -> Duration {
+        if self.rate == 0 {
+            return Duration::ZERO;
+        }
+        let rate = self.rate as f64;
+        if let Some(updated) = self.updated {
+            let refill = now.saturating_duration_since(updated).as_secs_f64() * rate;
+            self.tokens = (self.tokens + refill).min(rate);
+        }
+        self.updated = Some(now);
+        self.tokens -= amount as f64;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / rate)
+        }
+    }
+}
+
+impl Default for TokenBucket {
+    fn default() -> Self {
+        TokenBucket::new(0)
+    }
+}
+
+/// Uses the rate limits from the settings for all transfers, including
+/// the ones already running
+pub fn apply(settings: &Settings) {
+    TOTAL_RATE.store(settings.total_rate_limit * 1024, Ordering::SeqCst);
+    DOWNLOAD_RATE.store(settings.rate_limit_per_download * 1024, Ordering::SeqCst);
+}
+
+/// Throttles a single transfer to the limit per download and, together
+/// with all other transfers, to the total limit
+#[derive(Debug, Default)]
+pub struct Limiter {
+    own: TokenBucket,
+}
+
+impl Limiter {
+    /// Waits until `bytes` more may be transferred
+    pub async fn consume(&mut self, bytes: u64) {
+        let now = Instant::now();
+        self.own.set_rate(DOWNLOAD_RATE.load(Ordering::SeqCst));
+        let mut wait = self.own.take(bytes, now);
+        let total_rate = TOTAL_RATE.load(Ordering::SeqCst);
+        if total_rate > 0 {
+            let mut total = TOTAL.lock().unwrap_or_else(PoisonError::into_inner);
+            total.set_rate(total_rate);
+            wait = wait.max(total.take(bytes, now));
+        }
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unlimited_never_waits() {
+        let mut bucket = TokenBucket::new(0);
+        assert_eq!(bucket.take(u64::MAX, Instant::now()), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_burst_then_debt() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(1000);
+        assert_eq!(bucket.take(1000, start), Duration::ZERO);
+        assert_eq!(bucket.take(500, start), Duration::from_millis(500));
+        // after half a second the debt is paid
+        let later = start + Duration::from_millis(500);
+        assert_eq!(bucket.take(0, later), Duration::ZERO);
+        assert_eq!(bucket.take(2000, later), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_refill_is_capped_at_one_second() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(100);
+        bucket.take(100, start);
+        let later = start + Duration::from_secs(60);
+        assert_eq!(bucket.take(100, later), Duration::ZERO);
+        assert_eq!(bucket.take(100, later), Duration::from_secs(1));
+    }
+}
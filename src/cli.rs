@@ -0,0 +1,861 @@
+use crate::{
+    bandwidth,
+    disk::{self, SpaceError},
+    download::{self, DownloadError, RetryPolicy},
+    download_log::{DownloadLog, DownloadRecord, Episode, RecordStatus},
+    filename::{self, TemplateContext, TemplateError},
+    filesize,
+    filmliste::{self, FilmlisteError, ImportMode},
+    pagination::SearchItem,
+    search_backend::{self, BackendError, SearchBackend},
+    search_logic::{self, SearchOptions, SearchRequest},
+    settings::{Quality, Settings, SettingsError},
+    storage::StorageError,
+    subscriptions::{self, Subscriptions},
+    subtitles, utils,
+};
+use mediathekviewweb::models::{SortField, SortOrder};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+const USAGE: &str = "\
+Usage:
+  medow                                 start the graphical interface
+  medow search <query> [options]        search MediathekViewWeb
+  medow download <url|id>... [options]  download videos
+  medow import <file> [--diff]          import a MediathekView film list for offline searches
+  medow subscriptions [--json]          download new results of all enabled subscriptions
+
+Search options:
+  --channel <name>     restrict to a channel, can be repeated
+  --min <minutes>      minimum duration
+  --max <minutes>      maximum duration
+  --sort <field>       timestamp, duration or channel
+  --order <order>      asc or desc
+  --size <n>           number of results (default: page size from the settings)
+  --offset <n>         skip the first n results
+  --json               print the results as json
+
+Download options:
+  --dest <dir>         target folder (default: download folder from the settings)
+  --quality <quality>  preferred quality: hd, sd or lq
+  --query <query>      search the ids are looked up in, required for ids
+  --channel <name>     restrict the lookup to a channel, can be repeated
+  --subtitles          also download subtitles as .srt and .vtt
+  --json               print the downloaded files as json
+
+Import options:
+  --diff               add a diff list to the imported full list
+
+download and subscriptions only run inside the download window from the
+settings; outside it they exit with 0 without downloading anything.
+
+Exit codes: 0 success, 1 search or download failed, 2 invalid arguments";
+
+#[derive(Debug, thiserror::Error)]
+pub enum CliError {
+    #[error("{0}")]
+    Usage(String),
+    #[error("could not load settings: {0}")]
+    Settings(#[from] SettingsError),
+    #[error("search failed: {0}")]
+    Search(#[from] BackendError),
+    #[error("invalid filename template: {0}")]
+    Template(#[from] TemplateError),
+    #[error("{0}: download failed: {1}")]
+    Download(String, DownloadError),
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+    #[error(transparent)]
+    Space(#[from] SpaceError),
+    #[error("import failed: {0}")]
+    Import(#[from] FilmlisteError),
+    #[error("no result with id {0} found for the query")]
+    NotFound(String),
+    #[error("could not start the async runtime: {0}")]
+    Runtime(std::io::Error),
+}
+
+impl CliError {
+    /// Gets the exit code reported to the calling script
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CliError::Usage(_) => 2,
+            _ => 1,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchArgs {
+    pub search: SearchRequest,
+    pub channels: Vec<String>,
+    pub sort_field: Option<SortField>,
+    pub sort_order: Option<SortOrder>,
+    pub size: Option<usize>,
+    pub offset: usize,
+    pub json: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DownloadArgs {
+    /// video urls or ids of search results
+    pub targets: Vec<String>,
+    pub destination: Option<PathBuf>,
+    pub quality: Option<Quality>,
+    pub query: Option<String>,
+    pub channels: Vec<String>,
+    pub subtitles: bool,
+    pub json: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImportArgs {
+    pub path: PathBuf,
+    pub mode: ImportMode,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Help,
+    Search(SearchArgs),
+    Download(DownloadArgs),
+    Import(ImportArgs),
+    /// checks the subscriptions, printing json if set
+    Subscriptions {
+        json: bool,
+    },
+}
+
+/// Parses the command line without the program name.
+///
+/// Returns `None` if medow should start the graphical interface instead.
+pub fn parse_args(args: &[String]) -> Result<Option<Command>, CliError> {
+    let Some((command, rest)) = args.split_first() else {
+        return Ok(None);
+    };
+    let mut arguments = Arguments::parse(rest)?;
+    let command = match command.as_str() {
+        "help" | "--help" | "-h" | "--cli" => Command::Help,
+        "search" => Command::Search(SearchArgs {
+            search: SearchRequest {
+                query: arguments.positional.join(" "),
+                duration_min: arguments.number("min")?.map(|minutes| minutes as u64),
+                duration_max: arguments.number("max")?.map(|minutes| minutes as u64),
+            },
+            channels: arguments.values("channel"),
+            sort_field: match arguments.value("sort")?.as_deref() {
+                None => None,
+                Some("timestamp") => Some(SortField::Timestamp),
+                Some("duration") => Some(SortField::Duration),
+                Some("channel") => Some(SortField::Channel),
+                Some(other) => return Err(usage(format!("unknown sort field {other}"))),
+            },
+            sort_order: match arguments.value("order")?.as_deref() {
+                None => None,
+                Some("asc") => Some(SortOrder::Ascending),
+                Some("desc") => Some(SortOrder::Descending),
+                Some(other) => return Err(usage(format!("unknown sort order {other}"))),
+            },
+            size: arguments.number("size")?,
+            offset: arguments.number("offset")?.unwrap_or(0),
+            json: arguments.flag("json"),
+        }),
+        "download" => {
+            if arguments.positional.is_empty() {
+                return Err(usage("download needs at least one url or id"));
+            }
+            Command::Download(DownloadArgs {
+                targets: std::mem::take(&mut arguments.positional),
+                destination: arguments.value("dest")?.map(PathBuf::from),
+                quality: match arguments.value("quality")? {
+                    None => None,
+                    Some(label) => Some(
+                        Quality::from_label(&label.to_uppercase())
+                            .ok_or_else(|| usage(format!("unknown quality {label}")))?,
+                    ),
+                },
+                query: arguments.value("query")?,
+                channels: arguments.values("channel"),
+                subtitles: arguments.flag("subtitles"),
+                json: arguments.flag("json"),
+            })
+        }
+        "import" => {
+            let [path] = std::mem::take(&mut arguments.positional)
+                .try_into()
+                .map_err(|_| usage("import needs exactly one film list file"))?;
+            Command::Import(ImportArgs {
+                path: PathBuf::from(path),
+                mode: if arguments.flag("diff") {
+                    ImportMode::Diff
+                } else {
+                    ImportMode::Full
+                },
+            })
+        }
+        "subscriptions" => {
+            if let Some(extra) = arguments.positional.first() {
+                return Err(usage(format!("unexpected argument {extra}")));
+            }
+            Command::Subscriptions {
+                json: arguments.flag("json"),
+            }
+        }
+        other => return Err(usage(format!("unknown command {other}"))),
+    };
+    arguments.finish()?;
+    Ok(Some(command))
+}
+
+fn usage(message: impl Into<String>) -> CliError {
+    CliError::Usage(message.into())
+}
+
+// options given as `--name value`, `--name=value` or plain `--flag`
+struct Arguments {
+    positional: Vec<String>,
+    options: Vec<(String, Option<String>)>,
+}
+
+impl Arguments {
+    // options that are followed by a value
+    const WITH_VALUE: [&'static str; 10] = [
+        "channel", "min", "max", "sort", "order", "size", "offset", "dest", "quality", "query",
+    ];
+
+    fn parse(args: &[String]) -> Result<Arguments, CliError> {
+        let mut arguments = Arguments {
+            positional: Vec::new(),
+            options: Vec::new(),
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let Some(option) = arg.strip_prefix("--") else {
+                arguments.positional.push(arg.clone());
+                continue;
+            };
+            let (name, value) = match option.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None if Self::WITH_VALUE.contains(&option) => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| usage(format!("--{option} needs a value")))?;
+                    (option, Some(value.clone()))
+                }
+                None => (option, None),
+            };
+            arguments.options.push((name.to_string(), value));
+        }
+        Ok(arguments)
+    }
+
+    // takes all values of an option that may be repeated
+    fn values(&mut self, name: &str) -> Vec<String> {
+        let mut values = Vec::new();
+        self.options.retain(|(option, value)| {
+            if option != name {
+                return true;
+            }
+            values.extend(value.clone());
+            false
+        });
+        values
+    }
+
+    fn value(&mut self, name: &str) -> Result<Option<String>, CliError> {
+        let mut values = self.values(name);
+        match values.len() {
+            0 | 1 => Ok(values.pop()),
+            _ => Err(usage(format!("--{name} given more than once"))),
+        }
+    }
+
+    fn number(&mut self, name: &str) -> Result<Option<usize>, CliError> {
+        self.value(name)?
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| usage(format!("--{name} expects a number, got {value}")))
+            })
+            .transpose()
+    }
+
+    fn flag(&mut self, name: &str) -> bool {
+        let count = self.options.len();
+        self.options.retain(|(option, _)| option != name);
+        self.options.len() != count
+    }
+
+    // fails on options that weren't used by the command
+    fn finish(self) -> Result<(), CliError> {
+        match self.options.first() {
+            Some((name, _)) => Err(usage(format!("unknown option --{name}"))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Runs medow as a command line tool if a command was given.
+///
+/// Returns the exit code, or `None` to start the graphical interface.
+pub fn run(args: &[String]) -> Option<i32> {
+    let result = parse_args(args).and_then(|command| {
+        let Some(command) = command else {
+            return Ok(None);
+        };
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(CliError::Runtime)?;
+        runtime.block_on(execute(command)).map(Some)
+    });
+    match result {
+        Ok(None) => None,
+        Ok(Some(())) => Some(0),
+        Err(error) => {
+            eprintln!("medow: {error}");
+            if let CliError::Usage(_) = error {
+                eprintln!("\n{USAGE}");
+            }
+            Some(error.exit_code())
+        }
+    }
+}
+
+async fn execute(command: Command) -> Result<(), CliError> {
+    match command {
+        Command::Help => {
+            println!("{USAGE}");
+            Ok(())
+        }
+        Command::Search(args) => search(args).await,
+        Command::Download(args) => download(args).await,
+        Command::Import(args) => import(args).await,
+        Command::Subscriptions { json } => check_subscriptions(json).await,
+    }
+}
+
+/// A search result as printed with `--json`
+#[derive(Debug, Serialize)]
+struct ResultJson<'a> {
+    id: &'a str,
+    channel: &'a str,
+    topic: &'a str,
+    title: &'a str,
+    description: Option<&'a str>,
+    /// unix timestamp of the broadcast
+    timestamp: i64,
+    duration: &'a str,
+    size: Option<u64>,
+    website: &'a str,
+    subtitle: Option<&'a str>,
+    video_hd: Option<&'a str>,
+    video_sd: Option<&'a str>,
+    video_low: Option<&'a str>,
+}
+
+impl<'a> From<&'a SearchItem> for ResultJson<'a> {
+    fn from(item: &'a SearchItem) -> Self {
+        ResultJson {
+            id: &item.id,
+            channel: &item.channel,
+            topic: &item.topic,
+            title: &item.title,
+            description: item.description.as_deref(),
+            timestamp: item.published,
+            duration: &item.duration,
+            size: item.size,
+            website: &item.website_url,
+            subtitle: item.subtitle_url.as_deref(),
+            video_hd: item.video_urls.hd.as_deref(),
+            video_sd: item.video_urls.sd.as_deref(),
+            video_low: item.video_urls.low.as_deref(),
+        }
+    }
+}
+
+async fn search(args: SearchArgs) -> Result<(), CliError> {
+    let settings = Settings::load()?;
+    let mut options = SearchOptions::from_settings(&settings);
+    if !args.channels.is_empty() {
+        options.channels = args.channels;
+    }
+    options.sort_field = args.sort_field.unwrap_or(options.sort_field);
+    options.sort_order = args.sort_order.unwrap_or(options.sort_order);
+    let size = args.size.unwrap_or(settings.page_size);
+
+    let backend = search_backend::configured(&settings);
+    let results = backend
+        .search(&args.search, &options, args.offset, size)
+        .await?;
+    let (total, items) = (results.total, results.items);
+    if args.json {
+        let results: Vec<ResultJson> = items.iter().map(ResultJson::from).collect();
+        let output = serde_json::json!({ "total": total, "results": results });
+        println!("{output:#}");
+    } else {
+        for item in &items {
+            println!(
+                "{}\t{}\t{}\t{}\t{}\t{}",
+                item.id, item.timestamp, item.duration, item.channel, item.topic, item.title
+            );
+        }
+        eprintln!("{} of {total} results", items.len());
+    }
+    Ok(())
+}
+
+/// A downloaded file as printed with `--json`
+#[derive(Debug, Serialize)]
+struct DownloadJson<'a> {
+    url: &'a str,
+    path: &'a Path,
+    bytes: u64,
+}
+
+async fn import(args: ImportArgs) -> Result<(), CliError> {
+    // parsing a full list takes a while and blocks
+    let count = tokio::task::spawn_blocking(move || filmliste::import(&args.path, args.mode))
+        .await
+        .map_err(|error| CliError::Runtime(error.into()))??;
+    eprintln!("{count} films in the offline index");
+    Ok(())
+}
+
+async fn download(args: DownloadArgs) -> Result<(), CliError> {
+    let settings = Settings::load()?;
+    if window_closed(&settings, chrono::Utc::now()) {
+        return Ok(());
+    }
+    bandwidth::apply(&settings);
+    let mut options = SearchOptions::from_settings(&settings);
+    if !args.channels.is_empty() {
+        options.channels = args.channels.clone();
+    }
+    if let Some(quality) = args.quality {
+        options.quality_order.insert(0, quality);
+    }
+    let backend = search_backend::configured(&settings);
+    let items = resolve_targets(&backend, &args, &options).await?;
+
+    let transfer = Transfer::new(&settings)?;
+    let directory = args.destination.unwrap_or(settings.download_dir);
+    let subtitles = args.subtitles || settings.download_subtitles;
+    let template = &settings.filename_template;
+    download_items(
+        &transfer,
+        &items,
+        &directory,
+        template,
+        subtitles,
+        args.json,
+        |_| {},
+    )
+    .await
+}
+
+// the download window holds back downloads started by cron as well, which
+// then simply try again on their next run
+fn window_closed(settings: &Settings, now: chrono::DateTime<chrono::Utc>) -> bool {
+    let window = &settings.download_window;
+    let closed = !window.is_open(now);
+    if closed {
+        eprintln!(
+            "medow: outside the download window {}, nothing downloaded",
+            window.label()
+        );
+    }
+    closed
+}
+
+// the http client and retry policy shared by all downloads of a command
+struct Transfer {
+    client: reqwest::Client,
+    retry: RetryPolicy,
+}
+
+impl Transfer {
+    fn new(settings: &Settings) -> Result<Transfer, CliError> {
+        let client =
+            download::http_client().map_err(|error| CliError::Download(String::new(), error))?;
+        Ok(Transfer {
+            client,
+            retry: RetryPolicy::from_settings(settings),
+        })
+    }
+}
+
+// downloads the results one after another into `directory`, calling
+// `on_finished` after each finished video. Every download is added to the
+// download history, episodes downloaded before are fetched again with a warning.
+async fn download_items(
+    transfer: &Transfer,
+    items: &[SearchItem],
+    directory: &Path,
+    template: &str,
+    with_subtitles: bool,
+    json: bool,
+    mut on_finished: impl FnMut(&SearchItem),
+) -> Result<(), CliError> {
+    preflight(transfer, items, directory).await?;
+    let log = DownloadLog::load()?;
+    let mut destinations: Vec<PathBuf> = Vec::new();
+    for (position, item) in items.iter().enumerate() {
+        let episode = Episode::from_item(item);
+        if let Some(record) = log.finished(&episode) {
+            eprintln!(
+                "medow: {} was downloaded before to {}",
+                item.title,
+                record.destination.display()
+            );
+        }
+        let ext = filename::extension_from_url(&item.video_url);
+        let context = TemplateContext {
+            index: position + 1,
+            item,
+            ext: &ext,
+        };
+        let relative_path = filename::render(template, &context)?;
+        let destination = filename::unique_path(&directory.join(relative_path), |path| {
+            path.exists() || destinations.iter().any(|taken| taken == path)
+        });
+
+        let quality = Quality::from_label(&item.quality);
+        let result = download::fetch_with_retry(
+            &transfer.client,
+            &item.video_url,
+            &destination,
+            quality,
+            transfer.retry,
+            |_| {},
+            |attempt, error| {
+                eprintln!(
+                    "medow: {}: {error}, attempt {attempt} of {}",
+                    item.title, transfer.retry.attempts
+                )
+            },
+        )
+        .await;
+        DownloadLog::write(&DownloadRecord {
+            episode,
+            url: item.video_url.clone(),
+            destination: destination.clone(),
+            size: result.as_ref().ok().copied(),
+            status: match &result {
+                Ok(_) => RecordStatus::Finished,
+                Err(error) => RecordStatus::Failed(error.to_string()),
+            },
+            recorded: chrono::Utc::now().timestamp(),
+        })?;
+        let bytes = result.map_err(|error| CliError::Download(item.video_url.clone(), error))?;
+        if let (true, Some(url)) = (with_subtitles, &item.subtitle_url) {
+            // the video is there, so a missing subtitle is only reported
+            if let Err(error) = subtitles::download(&transfer.client, url, &destination).await {
+                eprintln!("medow: subtitles for {}: {error}", item.title);
+            }
+        }
+        on_finished(item);
+
+        if json {
+            let output = DownloadJson {
+                url: &item.video_url,
+                path: &destination,
+                bytes,
+            };
+            println!("{}", serde_json::to_string(&output).unwrap_or_default());
+        } else {
+            println!("{}", destination.display());
+        }
+        destinations.push(destination);
+    }
+    Ok(())
+}
+
+// refuses to start downloads that don't fit into `directory` next to what
+// the GUI's queue still fetches, and warns if little space would be left
+async fn preflight(
+    transfer: &Transfer,
+    items: &[SearchItem],
+    directory: &Path,
+) -> Result<(), CliError> {
+    if items.is_empty() {
+        return Ok(());
+    }
+    let urls = items.iter().map(|item| item.video_url.clone()).collect();
+    let (mut needed, mut unknown) = (0, 0);
+    filesize::probe_each(&transfer.client, urls, |_, size| match size {
+        Some(bytes) => needed += bytes,
+        None => unknown += 1,
+    })
+    .await;
+    if unknown > 0 {
+        eprintln!("medow: size of {unknown} of {} files unknown", items.len());
+    }
+    match download::saved_queue() {
+        Ok(queue) => needed += download::owed_bytes(&queue, directory, |_| None),
+        Err(error) => eprintln!("medow: {error}"),
+    }
+    match disk::check_space(directory, needed) {
+        Ok(left) if left < disk::RESERVE => eprintln!(
+            "medow: only {} will be left in {}",
+            utils::format_bytes(left),
+            directory.display()
+        ),
+        Ok(_) => {}
+        Err(error @ SpaceError::Unknown(..)) => eprintln!("medow: {error}"),
+        Err(error) => return Err(error.into()),
+    }
+    Ok(())
+}
+
+// downloads the new results of all enabled subscriptions, a failing
+// subscription doesn't keep the others from being checked
+async fn check_subscriptions(json: bool) -> Result<(), CliError> {
+    let settings = Settings::load()?;
+    // results aren't marked as downloaded before the window opens
+    if window_closed(&settings, chrono::Utc::now()) {
+        return Ok(());
+    }
+    bandwidth::apply(&settings);
+    let mut subscriptions = Subscriptions::load()?;
+    let log = DownloadLog::load()?;
+    let backend = search_backend::configured(&settings);
+    let transfer = Transfer::new(&settings)?;
+
+    let mut first_error = None;
+    for index in 0..subscriptions.subscriptions.len() {
+        let subscription = subscriptions.subscriptions[index].clone();
+        if !subscription.enabled {
+            continue;
+        }
+        let options = subscription.options(&settings);
+        let subscriptions::Check { new: results, seen } =
+            match subscriptions::check(&backend, &subscription, &options).await {
+                Ok(check) => check,
+                Err(error) => {
+                    eprintln!("medow: {}: {error}", subscription.name);
+                    first_error.get_or_insert(CliError::Search(error));
+                    continue;
+                }
+            };
+        // episodes downloaded by hand count as done, like in the window
+        let (mut finished, items): (Vec<_>, Vec<_>) = results
+            .into_iter()
+            .partition(|item| log.finished(&Episode::from_item(item)).is_some());
+        eprintln!("{}: {} new", subscription.name, items.len());
+
+        let result = download_items(
+            &transfer,
+            &items,
+            subscription.destination(&settings),
+            subscription.filename_template(&settings),
+            settings.download_subtitles,
+            json,
+            |item| finished.push(item.clone()),
+        )
+        .await;
+        let stored = &mut subscriptions.subscriptions[index];
+        stored.forget_unseen(&seen);
+        stored.mark_downloaded(&finished);
+        if let Err(error) = result {
+            eprintln!("medow: {}: {error}", subscription.name);
+            first_error.get_or_insert(error);
+        } else {
+            stored.last_checked = Some(chrono::Utc::now().timestamp());
+        }
+        subscriptions.save()?;
+    }
+    first_error.map_or(Ok(()), Err)
+}
+
+// turns the arguments into downloads, urls are taken as they are while ids
+// are looked up in the results of `--query`
+async fn resolve_targets(
+    backend: &impl SearchBackend,
+    args: &DownloadArgs,
+    options: &SearchOptions,
+) -> Result<Vec<SearchItem>, CliError> {
+    let ids: Vec<&String> = args
+        .targets
+        .iter()
+        .filter(|target| !is_url(target))
+        .collect();
+    let results = match (&args.query, ids.is_empty()) {
+        (_, true) => Vec::new(),
+        (Some(query), false) => {
+            let search = SearchRequest {
+                query: query.clone(),
+                ..Default::default()
+            };
+            search_logic::fetch_all(backend, &search, options, search_logic::SELECT_ALL_LIMIT)
+                .await?
+        }
+        (None, false) => {
+            return Err(usage(
+                "downloading by id needs the --query the id was found with",
+            ))
+        }
+    };
+
+    args.targets
+        .iter()
+        .map(|target| {
+            if is_url(target) {
+                Ok(item_for_url(target))
+            } else {
+                results
+                    .iter()
+                    .find(|item| &item.id == target)
+                    .cloned()
+                    .ok_or_else(|| CliError::NotFound(target.clone()))
+            }
+        })
+        .collect()
+}
+
+fn is_url(target: &str) -> bool {
+    target.starts_with("http://") || target.starts_with("https://")
+}
+
+// builds a result for a plain video url, named after its last path segment
+fn item_for_url(url: &str) -> SearchItem {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    let segment = path.rsplit('/').next().unwrap_or_default();
+    let title = match segment.rsplit_once('.') {
+        Some((stem, _)) if !stem.is_empty() => stem,
+        _ => segment,
+    };
+    SearchItem {
+        title: title.to_string(),
+        video_url: url.to_string(),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    fn parse(line: &str) -> Result<Option<Command>, CliError> {
+        parse_args(&args(line))
+    }
+
+    #[test]
+    fn test_no_arguments_start_gui() {
+        assert_eq!(parse("").unwrap(), None);
+    }
+
+    #[test]
+    fn test_parse_search() {
+        let Some(Command::Search(search)) =
+            parse("search tatort münster --channel ARD --channel=WDR --min 80 --json").unwrap()
+        else {
+            panic!("expected a search");
+        };
+        assert_eq!(search.search.query, "tatort münster");
+        assert_eq!(search.search.duration_min, Some(80));
+        assert_eq!(search.channels, vec!["ARD", "WDR"]);
+        assert!(search.json);
+        assert_eq!(search.offset, 0);
+    }
+
+    #[test]
+    fn test_parse_search_sort() {
+        let Some(Command::Search(search)) =
+            parse("search tatort --sort duration --order asc").unwrap()
+        else {
+            panic!("expected a search");
+        };
+        assert_eq!(search.sort_field, Some(SortField::Duration));
+        assert_eq!(search.sort_order, Some(SortOrder::Ascending));
+    }
+
+    #[test]
+    fn test_parse_download() {
+        let Some(Command::Download(download)) =
+            parse("download https://example.org/a.mp4 abc123 --dest /srv/media --quality hd --query tatort")
+                .unwrap()
+        else {
+            panic!("expected a download");
+        };
+        assert_eq!(
+            download.targets,
+            vec!["https://example.org/a.mp4", "abc123"]
+        );
+        assert_eq!(download.destination, Some(PathBuf::from("/srv/media")));
+        assert_eq!(download.quality, Some(Quality::HD));
+        assert_eq!(download.query.as_deref(), Some("tatort"));
+        assert!(!download.subtitles);
+    }
+
+    #[test]
+    fn test_parse_import() {
+        assert_eq!(
+            parse("import /srv/Filmliste-diff.xz --diff").unwrap(),
+            Some(Command::Import(ImportArgs {
+                path: PathBuf::from("/srv/Filmliste-diff.xz"),
+                mode: ImportMode::Diff,
+            }))
+        );
+        let Some(Command::Import(import)) = parse("import Filmliste-akt.xz").unwrap() else {
+            panic!("expected an import");
+        };
+        assert_eq!(import.mode, ImportMode::Full);
+    }
+
+    #[test]
+    fn test_parse_subscriptions() {
+        assert_eq!(
+            parse("subscriptions --json").unwrap(),
+            Some(Command::Subscriptions { json: true })
+        );
+    }
+
+    #[test]
+    fn test_parse_errors_are_usage_errors() {
+        for line in [
+            "download",
+            "search tatort --size many",
+            "search tatort --sort title",
+            "search tatort --bogus",
+            "search tatort --min",
+            "search tatort --offset 1 --offset 2",
+            "download x --quality 4k",
+            "import",
+            "import a.xz b.xz",
+            "subscriptions tatort",
+            "frobnicate",
+        ] {
+            let error = parse(line).unwrap_err();
+            assert_eq!(error.exit_code(), 2, "{line}");
+        }
+    }
+
+    #[test]
+    fn test_item_for_url() {
+        let item = item_for_url("https://example.org/videos/tatort_1080.mp4?token=1");
+        assert_eq!(item.title, "tatort_1080");
+        assert_eq!(
+            item.video_url,
+            "https://example.org/videos/tatort_1080.mp4?token=1"
+        );
+    }
+
+    #[test]
+    fn test_window_closed() {
+        use chrono::TimeZone;
+        let mut settings = Settings::default();
+        // 12:00 UTC is 13:00 in Berlin, outside the default 01:00–06:00
+        let noon = chrono::Utc.with_ymd_and_hms(2024, 1, 15, 12, 0, 0).unwrap();
+        assert!(!window_closed(&settings, noon));
+        settings.download_window.enabled = true;
+        assert!(window_closed(&settings, noon));
+        let night = chrono::Utc.with_ymd_and_hms(2024, 1, 15, 2, 0, 0).unwrap();
+        assert!(!window_closed(&settings, night));
+    }
+}
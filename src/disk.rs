@@ -0,0 +1,111 @@
+use crate::utils::format_bytes;
+use std::path::{Path, PathBuf};
+
+/// Free space that should be left on the destination after all downloads
+pub const RESERVE: u64 = 1024 * 1024 * 1024;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SpaceError {
+    #[error(
+        "not enough free space in {}: {} needed, only {} available",
+        .path.display(),
+        format_bytes(*.needed),
+        format_bytes(*.available)
+    )]
+    Insufficient {
+        path: PathBuf,
+        needed: u64,
+        available: u64,
+    },
+    #[error("could not determine the free space in {}: {}", .0.display(), .1)]
+    Unknown(PathBuf, std::io::Error),
+}
+
+/// Checks that `needed` bytes fit into the filesystem of `directory` and
+/// returns the space left afterwards, which callers warn about if it is
+/// below `RESERVE`
+pub fn check_space(directory: &Path, needed: u64) -> Result<u64, SpaceError> {
+    let available = available_space(directory)
+        .map_err(|error| SpaceError::Unknown(directory.to_path_buf(), error))?;
+    remaining(directory, needed, available)
+}
+
+fn remaining(directory: &Path, needed: u64, available: u64) -> Result<u64, SpaceError> {
+    available
+        .checked_sub(needed)
+        .ok_or_else(|| SpaceError::Insufficient {
+            path: directory.to_path_buf(),
+            needed,
+            available,
+        })
+}
+
+/// Gets the space available to normal users on the filesystem holding
+/// `path`; a folder that doesn't exist yet is looked up by its parents
+#[cfg(unix)]
+pub fn available_space(path: &Path) -> std::io::Result<u64> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let existing = existing_ancestor(path);
+    let c_path = std::ffi::CString::new(existing.as_os_str().as_bytes())?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: the path is a valid C string and `stat` is a writable statvfs
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    // the field types differ between platforms
+    #[allow(clippy::unnecessary_cast)]
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+pub fn available_space(_path: &Path) -> std::io::Result<u64> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+/// Identifies the filesystem holding `path`, looked up like in
+/// `available_space`; `None` if it can't be told
+#[cfg(unix)]
+pub fn device(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+
+    existing_ancestor(path)
+        .metadata()
+        .ok()
+        .map(|meta| meta.dev())
+}
+
+#[cfg(not(unix))]
+pub fn device(_path: &Path) -> Option<u64> {
+    None
+}
+
+fn existing_ancestor(path: &Path) -> &Path {
+    path.ancestors()
+        .find(|ancestor| ancestor.exists())
+        .unwrap_or(Path::new("."))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remaining() {
+        let path = Path::new("/srv/media");
+        assert_eq!(remaining(path, 100, 250).unwrap(), 150);
+        assert_eq!(remaining(path, 250, 250).unwrap(), 0);
+        let error = remaining(path, 4096, 2048).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "not enough free space in /srv/media: 4.0 KB needed, only 2.0 KB available"
+        );
+    }
+
+    #[test]
+    fn test_available_space_of_missing_folder() {
+        let missing = std::env::temp_dir().join("medow-missing/a/b");
+        assert!(available_space(&missing).is_ok());
+        assert_eq!(device(&missing), device(&std::env::temp_dir()));
+    }
+}
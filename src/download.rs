@@ -0,0 +1,1353 @@
+use crate::{
+    bandwidth::Limiter,
+    disk,
+    download_log::{DownloadLog, DownloadRecord, Episode, RecordStatus},
+    error,
+    filename::{self, TemplateContext, TemplateError},
+    hls::{self, HlsError},
+    pagination::SearchItem,
+    resume::{self, PartialMeta},
+    settings::{Quality, Settings},
+    storage::{self, StorageError},
+    subtitles, APP_STATE, DOWNLOADS, MEDOW_USER_AGENT,
+};
+use dioxus::{core::spawn_forever, prelude::*};
+use reqwest::header::{IF_RANGE, RANGE};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::hash_map::RandomState;
+use std::fmt::Display;
+use std::hash::{BuildHasher, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+
+// minimum time between two progress reports while a transfer is running
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+// how often a running download looks whether it was paused or cancelled
+const CONTROL_INTERVAL: Duration = Duration::from_millis(250);
+
+// first and longest wait between two attempts of a download
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(2);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(60);
+
+// how often queued downloads look whether the download window opened
+const SCHEDULE_INTERVAL: Duration = Duration::from_secs(30);
+
+// unfinished downloads, continued after a restart
+const QUEUE_FILE: &str = "queue.json";
+
+// number of download loops currently working through the queue
+static ACTIVE_WORKERS: AtomicUsize = AtomicUsize::new(0);
+
+// set while queue.json couldn't be read, so saving doesn't replace the
+// stored downloads before they were restored
+static QUEUE_LOCKED: AtomicBool = AtomicBool::new(false);
+
+// token handed to the next download loop that claims a download
+static NEXT_CLAIM: AtomicU64 = AtomicU64::new(1);
+
+// id handed to the next download added to the list
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Debug, thiserror::Error)]
+pub enum DownloadError {
+    #[error("no video url available")]
+    MissingUrl,
+    #[error("http request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("could not write file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("HLS stream: {0}")]
+    Hls(#[from] HlsError),
+    #[error("incomplete file: expected {expected} bytes, got {received}")]
+    Incomplete { expected: u64, received: u64 },
+    #[error("{source} (gave up after {attempts} attempts)")]
+    Exhausted {
+        attempts: u32,
+        source: Box<DownloadError>,
+    },
+}
+
+impl DownloadError {
+    /// Checks whether another attempt might succeed: timeouts, dropped
+    /// connections, server errors and truncated files are worth a retry
+    pub fn is_transient(&self) -> bool {
+        match self {
+            DownloadError::Http(error) => match error.status() {
+                Some(status) => {
+                    status.is_server_error()
+                        || status == StatusCode::REQUEST_TIMEOUT
+                        || status == StatusCode::TOO_MANY_REQUESTS
+                }
+                None => {
+                    error.is_timeout()
+                        || error.is_connect()
+                        || error.is_request()
+                        || error.is_body()
+                }
+            },
+            DownloadError::Incomplete { .. } | DownloadError::Hls(HlsError::Interrupted) => true,
+            _ => false,
+        }
+    }
+}
+
+/// How often transient failures are retried, waiting exponentially longer
+/// with random jitter in between
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// attempts in total, including the first one
+    pub attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub fn from_settings(settings: &Settings) -> Self {
+        RetryPolicy {
+            attempts: settings.download_attempts.max(1),
+            base_delay: RETRY_BASE_DELAY,
+            max_delay: RETRY_MAX_DELAY,
+        }
+    }
+
+    /// Gets the time to wait before the given attempt, 2 being the first
+    /// retry. `jitter` in `0.0..1.0` picks a point in the upper half of the
+    /// backoff, so parallel downloads don't retry all at once.
+    pub fn delay(&self, attempt: u32, jitter: f64) -> Duration {
+        let backoff = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(2)))
+            .min(self.max_delay);
+        backoff.mul_f64(0.5 + jitter.clamp(0.0, 1.0) / 2.0)
+    }
+}
+
+// random number in 0.0..1.0, std's hasher keys are random enough for jitter
+fn jitter() -> f64 {
+    let random = RandomState::new().build_hasher().finish();
+    (random >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Snapshot of a running transfer, handed to the progress callback
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Progress {
+    pub downloaded: u64,
+    pub total: Option<u64>,
+    /// average transfer rate in bytes per second
+    pub speed: f64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DownloadStatus {
+    Queued,
+    Running,
+    /// stopped by the user, the partial file is kept
+    Paused,
+    Finished,
+    Failed(String),
+    /// stopped by the user, the partial file is deleted
+    Cancelled,
+}
+
+/// Order in which queued downloads are started, list order breaks ties
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+impl Priority {
+    pub const ALL: [Priority; 3] = [Priority::High, Priority::Normal, Priority::Low];
+
+    /// Parses the label as shown in the UI
+    pub fn from_label(label: &str) -> Option<Priority> {
+        Priority::ALL
+            .into_iter()
+            .find(|priority| priority.to_string() == label)
+    }
+}
+
+impl Display for Priority {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Priority::High => f.write_str("high"),
+            Priority::Normal => f.write_str("normal"),
+            Priority::Low => f.write_str("low"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DownloadItem {
+    /// identifies the entry while it is moved around in the list
+    pub id: u64,
+    pub title: String,
+    pub topic: String,
+    pub video_url: String,
+    /// quality picked from the variants if the url is an HLS playlist
+    pub quality: Option<Quality>,
+    /// subtitles fetched after the video, if requested
+    pub subtitle_url: Option<String>,
+    pub destination: PathBuf,
+    /// identifies the episode for the download history and duplicate checks
+    pub episode: Episode,
+    pub priority: Priority,
+    pub status: DownloadStatus,
+    pub downloaded: u64,
+    pub total: Option<u64>,
+    #[serde(skip)]
+    pub speed: f64,
+    /// attempt running right now, above 1 after transient failures
+    #[serde(skip)]
+    pub attempt: u32,
+    /// token of the download loop whose transfer writes the file, until
+    /// the transfer is dropped; no other loop claims the download meanwhile
+    #[serde(skip)]
+    pub claim: Option<u64>,
+}
+
+impl DownloadItem {
+    /// Creates a queued download for a search result, stored at the given path
+    pub fn new(item: &SearchItem, destination: PathBuf) -> Self {
+        DownloadItem {
+            id: NEXT_ID.fetch_add(1, Ordering::SeqCst),
+            title: item.title.clone(),
+            topic: item.topic.clone(),
+            video_url: item.video_url.clone(),
+            quality: Quality::from_label(&item.quality),
+            subtitle_url: item.subtitle_url.clone(),
+            destination,
+            episode: Episode::from_item(item),
+            priority: Priority::default(),
+            status: DownloadStatus::Queued,
+            downloaded: 0,
+            total: None,
+            speed: 0.0,
+            attempt: 0,
+            claim: None,
+        }
+    }
+
+    /// Gets the progress in percent if the total size is known
+    pub fn percent(&self) -> Option<f64> {
+        match self.total {
+            Some(total) if total > 0 => Some(self.downloaded as f64 * 100.0 / total as f64),
+            _ => None,
+        }
+    }
+
+    /// Estimates the remaining time based on the current speed
+    pub fn eta(&self) -> Option<Duration> {
+        let total = self.total?;
+        if self.speed <= 0.0 {
+            return None;
+        }
+        let remaining = total.saturating_sub(self.downloaded);
+        Some(Duration::from_secs_f64(remaining as f64 / self.speed))
+    }
+}
+
+/// Gets the directory downloads are stored in
+pub fn default_download_dir() -> PathBuf {
+    dirs::download_dir()
+        .or_else(dirs::home_dir)
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// Creates the http client used for all downloads
+pub fn http_client() -> Result<reqwest::Client, DownloadError> {
+    Ok(reqwest::Client::builder()
+        .user_agent(MEDOW_USER_AGENT)
+        .build()?)
+}
+
+/// Downloads a video into the file at `destination`, either as a plain
+/// file or, for `.m3u8` urls, as an HLS stream in the given quality
+pub async fn fetch(
+    client: &reqwest::Client,
+    url: &str,
+    destination: &Path,
+    quality: Option<Quality>,
+    on_progress: impl FnMut(Progress),
+) -> Result<u64, DownloadError> {
+    if hls::is_playlist(url) {
+        hls::download(client, url, destination, quality, on_progress).await
+    } else {
+        fetch_to_file(client, url, destination, on_progress).await
+    }
+}
+
+/// Like `fetch`, but tries again after transient failures as long as the
+/// policy allows. `on_retry` gets the number of the coming attempt and the
+/// error that made it necessary; a partial plain file is continued.
+pub async fn fetch_with_retry(
+    client: &reqwest::Client,
+    url: &str,
+    destination: &Path,
+    quality: Option<Quality>,
+    policy: RetryPolicy,
+    mut on_progress: impl FnMut(Progress),
+    mut on_retry: impl FnMut(u32, &DownloadError),
+) -> Result<u64, DownloadError> {
+    let mut attempt = 1;
+    loop {
+        match fetch(client, url, destination, quality, &mut on_progress).await {
+            Err(error) if error.is_transient() && attempt < policy.attempts => {
+                attempt += 1;
+                on_retry(attempt, &error);
+                tokio::time::sleep(policy.delay(attempt, jitter())).await;
+            }
+            Err(error) if error.is_transient() && attempt > 1 => {
+                return Err(DownloadError::Exhausted {
+                    attempts: attempt,
+                    source: Box::new(error),
+                });
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Streams the resource behind `url` into the file at `destination`,
+/// reporting progress roughly every 250ms and once after the last chunk.
+///
+/// Data is written to a `.part` file first, which is picked up again by a
+/// later attempt if the server supports range requests and the remote file
+/// did not change in between. It only replaces `destination` once its size
+/// matches the announced length.
+pub async fn fetch_to_file(
+    client: &reqwest::Client,
+    url: &str,
+    destination: &Path,
+    mut on_progress: impl FnMut(Progress),
+) -> Result<u64, DownloadError> {
+    if url.is_empty() {
+        return Err(DownloadError::MissingUrl);
+    }
+    if let Some(parent) = destination.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let part = resume::part_path(destination);
+
+    let mut offset = resume_offset(client, url, destination, &part).await;
+    let mut request = client.get(url);
+    if let Some((existing, validator)) = &offset {
+        request = request.header(RANGE, format!("bytes={existing}-"));
+        if let Some(validator) = validator {
+            request = request.header(IF_RANGE, validator);
+        }
+    }
+    let mut response = request.send().await?.error_for_status()?;
+
+    // the server may ignore the range and send the whole file instead
+    let mut total = response.content_length();
+    if let (Some((existing, _)), StatusCode::PARTIAL_CONTENT) = (&offset, response.status()) {
+        match resume::content_range(response.headers()) {
+            Some((start, complete)) if start == *existing => {
+                total = complete.or(total.map(|length| length + start))
+            }
+            _ => offset = None,
+        }
+    } else {
+        offset = None;
+    }
+
+    let remote = resume::RemoteInfo::from_headers(response.headers());
+    resume::save_meta(
+        destination,
+        &PartialMeta {
+            url: url.to_string(),
+            etag: remote.etag,
+            last_modified: remote.last_modified,
+            total,
+        },
+    )
+    .await?;
+
+    let (mut file, resumed_at) = match offset {
+        Some((existing, _)) => {
+            let file = tokio::fs::OpenOptions::new()
+                .append(true)
+                .open(&part)
+                .await?;
+            (file, existing)
+        }
+        None => (tokio::fs::File::create(&part).await?, 0),
+    };
+
+    let started = Instant::now();
+    let mut last_report = started;
+    let mut downloaded = resumed_at;
+    let mut limiter = Limiter::default();
+    while let Some(chunk) = response.chunk().await? {
+        limiter.consume(chunk.len() as u64).await;
+        file.write_all(&chunk).await?;
+        downloaded += chunk.len() as u64;
+        if last_report.elapsed() >= PROGRESS_INTERVAL {
+            last_report = Instant::now();
+            on_progress(Progress {
+                downloaded,
+                total,
+                speed: (downloaded - resumed_at) as f64 / started.elapsed().as_secs_f64(),
+            });
+        }
+    }
+    file.flush().await?;
+    drop(file);
+
+    // a connection closed early must not pass as a finished file; the part
+    // file is kept, so the next attempt continues it
+    let expected = total.unwrap_or(downloaded);
+    let received = tokio::fs::metadata(&part).await?.len();
+    if received != expected {
+        return Err(DownloadError::Incomplete { expected, received });
+    }
+
+    tokio::fs::rename(&part, destination).await?;
+    resume::remove_meta(destination).await;
+
+    on_progress(Progress {
+        downloaded,
+        total: total.or(Some(downloaded)),
+        speed: (downloaded - resumed_at) as f64 / started.elapsed().as_secs_f64().max(f64::EPSILON),
+    });
+    Ok(downloaded)
+}
+
+// checks whether an existing part file can be continued and returns its
+// length together with the validator to send along as `If-Range`
+async fn resume_offset(
+    client: &reqwest::Client,
+    url: &str,
+    destination: &Path,
+    part: &Path,
+) -> Option<(u64, Option<String>)> {
+    let existing = tokio::fs::metadata(part).await.ok()?.len();
+    let stored = resume::load_meta(destination).await?;
+    if existing == 0 || stored.url != url {
+        return None;
+    }
+    let response = client
+        .head(url)
+        .send()
+        .await
+        .ok()?
+        .error_for_status()
+        .ok()?;
+    let remote = resume::RemoteInfo::from_headers(response.headers());
+    if !resume::can_resume(existing, &stored, &remote) {
+        return None;
+    }
+    Some((existing, remote.if_range().map(str::to_string)))
+}
+
+/// Why a result would be downloaded a second time
+#[derive(Clone, Debug, PartialEq)]
+pub enum Duplicate {
+    /// waiting, paused or running in the download list
+    Queued,
+    /// downloaded before, stored at the given path
+    Downloaded(PathBuf),
+}
+
+/// What `enqueue` does with results that were downloaded or queued before
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Duplicates {
+    Skip,
+    Download,
+}
+
+/// Checks whether a result, in any quality, is queued or was downloaded before
+pub fn find_duplicate(item: &SearchItem) -> Option<Duplicate> {
+    let log = &APP_STATE.read().download_log;
+    duplicate_in(&Episode::from_item(item), &DOWNLOADS.read(), log)
+}
+
+fn duplicate_in(episode: &Episode, queue: &[DownloadItem], log: &DownloadLog) -> Option<Duplicate> {
+    // failed downloads may be queued again
+    let queued = queue.iter().any(|download| {
+        matches!(
+            download.status,
+            DownloadStatus::Queued | DownloadStatus::Running | DownloadStatus::Paused
+        ) && download.episode.is_same(episode)
+    });
+    if queued {
+        return Some(Duplicate::Queued);
+    }
+    log.finished(episode)
+        .map(|record| Duplicate::Downloaded(record.destination.clone()))
+}
+
+/// Adds the given search results to the download list and makes sure
+/// the download loop is running.
+///
+/// Filenames are built from `template` inside `directory`; names already used
+/// by a finished file or another queued download get a counter appended.
+/// Subtitles are only fetched if enabled in the settings. Returns the number
+/// of queued downloads, which is smaller than the number of results if
+/// duplicates are skipped.
+pub fn enqueue(
+    items: &[SearchItem],
+    directory: &Path,
+    template: &str,
+    duplicates: Duplicates,
+) -> Result<usize, TemplateError> {
+    let items: Vec<&SearchItem> = match duplicates {
+        Duplicates::Download => items.iter().collect(),
+        Duplicates::Skip => {
+            let mut kept: Vec<&SearchItem> = Vec::new();
+            for item in items {
+                let episode = Episode::from_item(item);
+                let in_batch = kept
+                    .iter()
+                    .any(|other| Episode::from_item(other).is_same(&episode));
+                if !in_batch && find_duplicate(item).is_none() {
+                    kept.push(item);
+                }
+            }
+            kept
+        }
+    };
+
+    let relative_paths = items
+        .iter()
+        .enumerate()
+        .map(|(position, item)| {
+            let ext = filename::extension_from_url(&item.video_url);
+            let context = TemplateContext {
+                index: position + 1,
+                item,
+                ext: &ext,
+            };
+            filename::render(template, &context)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let with_subtitles = APP_STATE.read().settings.download_subtitles;
+    let mut downloads = DOWNLOADS.write();
+    for (item, relative_path) in items.iter().zip(relative_paths) {
+        let destination = filename::unique_path(&directory.join(relative_path), |path| {
+            path.exists() || downloads.iter().any(|queued| queued.destination == path)
+        });
+        let mut download = DownloadItem::new(item, destination);
+        if !with_subtitles {
+            download.subtitle_url = None;
+        }
+        downloads.push(download);
+    }
+    drop(downloads);
+
+    save_queue();
+    start_runner();
+    Ok(items.len())
+}
+
+/// Queues a failed or cancelled download again, continuing its partial file if possible
+pub fn retry(id: u64) {
+    let retried = update(id, |item| {
+        let stopped = matches!(
+            item.status,
+            DownloadStatus::Failed(_) | DownloadStatus::Cancelled
+        );
+        if stopped {
+            item.status = DownloadStatus::Queued;
+        }
+        stopped
+    });
+    if retried {
+        start_runner();
+    }
+}
+
+/// Stops a queued or running download, keeping what was downloaded so far
+pub fn pause(id: u64) {
+    update(id, |item| {
+        let active = matches!(
+            item.status,
+            DownloadStatus::Queued | DownloadStatus::Running
+        );
+        if active {
+            item.status = DownloadStatus::Paused;
+        }
+        active
+    });
+}
+
+/// Queues a paused download again
+pub fn resume(id: u64) {
+    let resumed = update(id, |item| {
+        let paused = item.status == DownloadStatus::Paused;
+        if paused {
+            item.status = DownloadStatus::Queued;
+        }
+        paused
+    });
+    if resumed {
+        start_runner();
+    }
+}
+
+/// Stops a download for good and deletes its partial file.
+///
+/// A running download is stopped by its download loop, which removes the
+/// partial file once the transfer is dropped.
+pub fn cancel(id: u64) {
+    let mut was_running = false;
+    let mut destination = None;
+    let cancelled = update(id, |item| match item.status {
+        DownloadStatus::Finished | DownloadStatus::Cancelled => false,
+        _ => {
+            was_running = item.status == DownloadStatus::Running;
+            destination = Some(item.destination.clone());
+            item.status = DownloadStatus::Cancelled;
+            true
+        }
+    });
+    if let (true, false, Some(destination)) = (cancelled, was_running, destination) {
+        spawn_forever(async move { discard_partial(&destination).await });
+    }
+}
+
+/// Moves a download up (negative offset) or down in the list
+pub fn move_by(id: u64, offset: isize) {
+    if move_in(&mut DOWNLOADS.write(), id, offset) {
+        save_queue();
+    }
+}
+
+pub fn set_priority(id: u64, priority: Priority) {
+    update(id, |item| {
+        item.priority = priority;
+        true
+    });
+}
+
+/// Removes finished and cancelled downloads from the list
+pub fn clear_done() {
+    DOWNLOADS.write().retain(|item| !is_done(item));
+}
+
+/// Writes the unfinished downloads to disk so they are continued after a restart.
+///
+/// Skipped while the stored queue couldn't be restored, the restore error
+/// offers the retry.
+pub fn save_queue() {
+    if QUEUE_LOCKED.load(Ordering::SeqCst) {
+        return;
+    }
+    let pending: Vec<DownloadItem> = DOWNLOADS
+        .read()
+        .iter()
+        .filter(|item| !is_done(item))
+        .cloned()
+        .collect();
+    if let Err(error) = storage::save_json(QUEUE_FILE, &pending) {
+        error::report_with_retry(error, save_queue);
+    }
+}
+
+/// Puts the downloads left over from the last session in front of the list
+/// and continues them
+pub fn restore_queue() {
+    match storage::load_json::<Vec<DownloadItem>>(QUEUE_FILE) {
+        Ok(pending) => {
+            let restored = restored(pending);
+            DOWNLOADS.write().splice(0..0, restored);
+            // downloads added meanwhile weren't saved yet
+            if QUEUE_LOCKED.swap(false, Ordering::SeqCst) {
+                save_queue();
+            }
+            start_runner();
+        }
+        // a queue that can't be parsed is moved aside, one that can't be
+        // read right now is kept until the retry works
+        Err(error) => match storage::set_aside(error) {
+            error @ StorageError::BackedUp(..) => error::report(error),
+            error => {
+                QUEUE_LOCKED.store(true, Ordering::SeqCst);
+                error::report_with_retry(error, restore_queue);
+            }
+        },
+    }
+}
+
+// prepares downloads read from disk: nothing runs yet after a restart, and
+// ids are handed out again so they can't clash with downloads added meanwhile
+fn restored(pending: Vec<DownloadItem>) -> Vec<DownloadItem> {
+    pending
+        .into_iter()
+        .map(|mut item| {
+            if item.status == DownloadStatus::Running {
+                item.status = DownloadStatus::Queued;
+            }
+            item.id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+            item.speed = 0.0;
+            item
+        })
+        .collect()
+}
+
+/// Adds up what the unfinished downloads into the filesystem of `directory`
+/// still have to fetch; `probed` gives the size if the server sent none yet
+pub fn owed_bytes(
+    queue: &[DownloadItem],
+    directory: &Path,
+    probed: impl Fn(&str) -> Option<u64>,
+) -> u64 {
+    let device = disk::device(directory);
+    queue
+        .iter()
+        .filter(|item| {
+            matches!(
+                item.status,
+                DownloadStatus::Queued | DownloadStatus::Running | DownloadStatus::Paused
+            )
+        })
+        .filter(|item| {
+            // counted unless the destination is known to be elsewhere
+            let other = item.destination.parent().and_then(disk::device);
+            device.is_none() || other.is_none() || other == device
+        })
+        .filter_map(|item| {
+            let total = item.total.or_else(|| probed(&item.video_url))?;
+            Some(total.saturating_sub(item.downloaded))
+        })
+        .sum()
+}
+
+/// Reads the downloads left over from the GUI, for the command line to take
+/// them into account
+pub fn saved_queue() -> Result<Vec<DownloadItem>, StorageError> {
+    storage::load_json(QUEUE_FILE)
+}
+
+fn is_done(item: &DownloadItem) -> bool {
+    matches!(
+        item.status,
+        DownloadStatus::Finished | DownloadStatus::Cancelled
+    )
+}
+
+// changes the download with the given id and saves the queue if `change`
+// reports that something changed
+fn update(id: u64, change: impl FnOnce(&mut DownloadItem) -> bool) -> bool {
+    let changed = match DOWNLOADS.write().iter_mut().find(|item| item.id == id) {
+        Some(item) => {
+            let changed = change(item);
+            if changed {
+                item.speed = 0.0;
+            }
+            changed
+        }
+        None => false,
+    };
+    if changed {
+        save_queue();
+    }
+    changed
+}
+
+fn move_in(downloads: &mut [DownloadItem], id: u64, offset: isize) -> bool {
+    let Some(from) = downloads.iter().position(|item| item.id == id) else {
+        return false;
+    };
+    let to = from.saturating_add_signed(offset).min(downloads.len() - 1);
+    if from == to {
+        return false;
+    }
+    if from < to {
+        downloads[from..=to].rotate_left(1);
+    } else {
+        downloads[to..=from].rotate_right(1);
+    }
+    true
+}
+
+/// Starts as many download loops as the settings allow; loops above a
+/// lowered limit stop after their current download
+pub fn start_runner() {
+    let limit = APP_STATE.read().settings.max_concurrent_downloads.max(1);
+    while ACTIVE_WORKERS.load(Ordering::SeqCst) < limit {
+        ACTIVE_WORKERS.fetch_add(1, Ordering::SeqCst);
+        // the loop has to outlive the view that queued the downloads
+        spawn_forever(run_queue());
+    }
+}
+
+// ends a download loop if more are running than the settings allow
+fn retire_surplus_worker() -> bool {
+    let limit = APP_STATE.read().settings.max_concurrent_downloads.max(1);
+    ACTIVE_WORKERS
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |active| {
+            (active > limit).then(|| active - 1)
+        })
+        .is_ok()
+}
+
+// resolves once a running download was paused, cancelled or removed, or
+// the download window closed
+async fn stopped(id: u64, claim: u64) {
+    loop {
+        tokio::time::sleep(CONTROL_INTERVAL).await;
+        let running = DOWNLOADS
+            .read()
+            .iter()
+            .any(|item| holds(item, id, claim) && item.status == DownloadStatus::Running);
+        if !running || !window_open() {
+            return;
+        }
+    }
+}
+
+/// Checks whether the download window from the settings allows downloads now
+pub fn window_open() -> bool {
+    APP_STATE
+        .read()
+        .settings
+        .download_window
+        .is_open(chrono::Utc::now())
+}
+
+// deletes the partial file of a cancelled download
+async fn discard_partial(destination: &Path) {
+    let _ = tokio::fs::remove_file(resume::part_path(destination)).await;
+    resume::remove_meta(destination).await;
+}
+
+// downloads the subtitles of a finished video, offering a retry if that fails
+async fn fetch_subtitles(client: reqwest::Client, url: String, video: PathBuf) {
+    if let Err(error) = subtitles::download(&client, &url, &video).await {
+        error::report_with_retry(error, move || {
+            spawn_forever(fetch_subtitles(client.clone(), url.clone(), video.clone()));
+        });
+    }
+}
+
+// works through queued downloads until none are left
+async fn run_queue() {
+    let client = match http_client() {
+        Ok(client) => client,
+        Err(error) => {
+            for item in DOWNLOADS.write().iter_mut() {
+                if item.status == DownloadStatus::Queued {
+                    item.status = DownloadStatus::Failed(error.to_string());
+                }
+            }
+            save_queue();
+            ACTIVE_WORKERS.fetch_sub(1, Ordering::SeqCst);
+            return;
+        }
+    };
+
+    loop {
+        if retire_surplus_worker() {
+            return;
+        }
+        if !window_open() {
+            if !DOWNLOADS
+                .read()
+                .iter()
+                .any(|item| item.status == DownloadStatus::Queued)
+            {
+                break;
+            }
+            tokio::time::sleep(SCHEDULE_INTERVAL).await;
+            continue;
+        }
+        let Some((download, claim)) = claim_next_queued() else {
+            break;
+        };
+        let id = download.id;
+        let policy = RetryPolicy::from_settings(&APP_STATE.read().settings);
+        let transfer = fetch_with_retry(
+            &client,
+            &download.video_url,
+            &download.destination,
+            download.quality,
+            policy,
+            |progress| {
+                let mut downloads = DOWNLOADS.write();
+                if let Some(item) = downloads.iter_mut().find(|item| holds(item, id, claim)) {
+                    item.downloaded = progress.downloaded;
+                    item.total = progress.total;
+                    item.speed = progress.speed;
+                }
+            },
+            |attempt, _| {
+                let mut downloads = DOWNLOADS.write();
+                if let Some(item) = downloads.iter_mut().find(|item| holds(item, id, claim)) {
+                    item.attempt = attempt;
+                    item.speed = 0.0;
+                }
+            },
+        );
+        // dropping the transfer stops it, the partial file stays for a resume
+        let result = tokio::select! {
+            result = transfer => Some(result),
+            _ = stopped(id, claim) => None,
+        };
+        let Some(result) = result else {
+            let cancelled = DOWNLOADS
+                .read()
+                .iter()
+                .any(|item| holds(item, id, claim) && item.status == DownloadStatus::Cancelled);
+            if cancelled {
+                discard_partial(&download.destination).await;
+            }
+            // stopped by the end of the download window, continued in the next one
+            release(id, claim, |item| {
+                if item.status == DownloadStatus::Running {
+                    item.status = DownloadStatus::Queued;
+                }
+            });
+            continue;
+        };
+
+        // a missing subtitle should not throw away the finished video
+        if let (Ok(_), Some(subtitle_url)) = (&result, download.subtitle_url) {
+            fetch_subtitles(client.clone(), subtitle_url, download.destination.clone()).await;
+        }
+
+        let (status, size) = match result {
+            Ok(bytes) => (RecordStatus::Finished, Some(bytes)),
+            Err(error) => (RecordStatus::Failed(error.to_string()), None),
+        };
+        release(id, claim, |item| {
+            item.status = match &status {
+                RecordStatus::Finished => DownloadStatus::Finished,
+                RecordStatus::Failed(reason) => DownloadStatus::Failed(reason.clone()),
+            };
+        });
+        log_download(DownloadRecord {
+            episode: download.episode,
+            url: download.video_url,
+            destination: download.destination,
+            size,
+            status,
+            recorded: chrono::Utc::now().timestamp(),
+        });
+    }
+    ACTIVE_WORKERS.fetch_sub(1, Ordering::SeqCst);
+}
+
+// remembers a finished or failed download in the download history
+fn log_download(record: DownloadRecord) {
+    let written = APP_STATE.write().download_log.record(record.clone());
+    if let Err(error) = written {
+        error::report_with_retry(error, move || write_log(record.clone()));
+    }
+}
+
+// appends a record the download history failed to write before
+fn write_log(record: DownloadRecord) {
+    if let Err(error) = DownloadLog::write(&record) {
+        error::report_with_retry(error, move || write_log(record.clone()));
+    }
+}
+
+// marks the next queued download as running so no other loop picks it up,
+// returning it together with the token of this claim
+fn claim_next_queued() -> Option<(DownloadItem, u64)> {
+    let mut downloads = DOWNLOADS.write();
+    let index = next_queued(&downloads)?;
+    let claim = NEXT_CLAIM.fetch_add(1, Ordering::SeqCst);
+    let item = &mut downloads[index];
+    item.status = DownloadStatus::Running;
+    item.attempt = 1;
+    item.claim = Some(claim);
+    Some((item.clone(), claim))
+}
+
+// whether `item` is the download `id` as claimed by the loop holding `claim`;
+// a download paused and resumed meanwhile may already run under a new claim
+fn holds(item: &DownloadItem, id: u64, claim: u64) -> bool {
+    item.id == id && item.claim == Some(claim)
+}
+
+// hands a download back after its transfer was dropped, applying `change`
+// only if the claim is still the one the loop holds
+fn release(id: u64, claim: u64, change: impl FnOnce(&mut DownloadItem)) {
+    update(id, |item| {
+        let held = item.claim == Some(claim);
+        if held {
+            item.claim = None;
+            change(item);
+        }
+        held
+    });
+}
+
+// picks the queued download with the highest priority, the first one in the
+// list among equals; one whose last transfer is still being dropped waits
+fn next_queued(downloads: &[DownloadItem]) -> Option<usize> {
+    downloads
+        .iter()
+        .enumerate()
+        .filter(|(_, item)| item.status == DownloadStatus::Queued && item.claim.is_none())
+        .min_by_key(|(_, item)| Reverse(item.priority))
+        .map(|(index, _)| index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, block_on, Response};
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    fn item(downloaded: u64, total: Option<u64>, speed: f64) -> DownloadItem {
+        DownloadItem {
+            id: 0,
+            title: String::from("title"),
+            topic: String::from("topic"),
+            video_url: String::new(),
+            quality: None,
+            subtitle_url: None,
+            destination: PathBuf::new(),
+            episode: Episode::default(),
+            priority: Priority::Normal,
+            status: DownloadStatus::Running,
+            downloaded,
+            total,
+            speed,
+            attempt: 1,
+            claim: None,
+        }
+    }
+
+    #[test]
+    fn test_percent_unknown_total() {
+        assert_eq!(item(100, None, 0.0).percent(), None);
+    }
+
+    #[test]
+    fn test_percent() {
+        assert_eq!(item(50, Some(200), 0.0).percent(), Some(25.0));
+    }
+
+    #[test]
+    fn test_eta() {
+        assert_eq!(
+            item(100, Some(300), 50.0).eta(),
+            Some(Duration::from_secs(4))
+        );
+    }
+
+    #[test]
+    fn test_eta_without_speed() {
+        assert_eq!(item(100, Some(300), 0.0).eta(), None);
+    }
+
+    #[test]
+    fn test_owed_bytes_of_pending_downloads() {
+        let directory = std::env::temp_dir();
+        let pending = |downloaded, total, status, url: &str| DownloadItem {
+            status,
+            video_url: String::from(url),
+            destination: directory.join("video.mp4"),
+            ..item(downloaded, total, 0.0)
+        };
+        let queue = [
+            pending(100, Some(300), DownloadStatus::Running, "a"),
+            pending(0, Some(1000), DownloadStatus::Queued, "b"),
+            pending(50, Some(80), DownloadStatus::Paused, "c"),
+            // no total yet, the probed size is used
+            pending(0, None, DownloadStatus::Queued, "probed"),
+            pending(0, None, DownloadStatus::Queued, "unknown"),
+            // nothing more to fetch for these
+            pending(300, Some(300), DownloadStatus::Finished, "d"),
+            pending(
+                10,
+                Some(300),
+                DownloadStatus::Failed(String::from("404")),
+                "e",
+            ),
+            pending(10, Some(300), DownloadStatus::Cancelled, "f"),
+        ];
+        let probed = |url: &str| (url == "probed").then_some(500);
+        assert_eq!(
+            owed_bytes(&queue, &directory, probed),
+            200 + 1000 + 30 + 500
+        );
+        assert_eq!(owed_bytes(&[], &directory, probed), 0);
+    }
+
+    #[test]
+    fn test_duplicate_in_queue_and_log() {
+        let result = SearchItem {
+            id: String::from("a"),
+            video_url: String::from("https://example.org/a_hd.mp4"),
+            ..Default::default()
+        };
+        let episode = Episode::from_item(&result);
+        let mut log = DownloadLog::default();
+        assert_eq!(duplicate_in(&episode, &[], &log), None);
+
+        let mut queued = DownloadItem::new(&result, PathBuf::from("/srv/a.mp4"));
+        assert_eq!(
+            duplicate_in(&episode, std::slice::from_ref(&queued), &log),
+            Some(Duplicate::Queued)
+        );
+        queued.status = DownloadStatus::Failed(String::from("timeout"));
+        assert_eq!(duplicate_in(&episode, &[queued], &log), None);
+
+        log.records.push(DownloadRecord {
+            episode: episode.clone(),
+            url: result.video_url.clone(),
+            destination: PathBuf::from("/srv/a.mp4"),
+            size: Some(10),
+            status: RecordStatus::Finished,
+            recorded: 0,
+        });
+        assert_eq!(
+            duplicate_in(&episode, &[], &log),
+            Some(Duplicate::Downloaded(PathBuf::from("/srv/a.mp4")))
+        );
+    }
+
+    fn queued(id: u64, priority: Priority) -> DownloadItem {
+        DownloadItem {
+            id,
+            priority,
+            status: DownloadStatus::Queued,
+            ..item(0, None, 0.0)
+        }
+    }
+
+    #[test]
+    fn test_next_queued_prefers_priority_then_order() {
+        let mut downloads = vec![
+            queued(1, Priority::Low),
+            queued(2, Priority::Normal),
+            queued(3, Priority::Normal),
+        ];
+        assert_eq!(next_queued(&downloads), Some(1));
+        downloads[2].priority = Priority::High;
+        assert_eq!(next_queued(&downloads), Some(2));
+        downloads[2].status = DownloadStatus::Paused;
+        downloads[1].status = DownloadStatus::Running;
+        assert_eq!(next_queued(&downloads), Some(0));
+        downloads[0].status = DownloadStatus::Finished;
+        assert_eq!(next_queued(&downloads), None);
+    }
+
+    #[test]
+    fn test_resumed_download_waits_for_old_claim() {
+        // paused and resumed before the old loop noticed: still held by claim 7
+        let mut downloads = vec![DownloadItem {
+            claim: Some(7),
+            ..queued(1, Priority::Normal)
+        }];
+        assert_eq!(next_queued(&downloads), None);
+        assert!(holds(&downloads[0], 1, 7));
+        assert!(!holds(&downloads[0], 1, 8));
+
+        downloads[0].claim = None;
+        assert_eq!(next_queued(&downloads), Some(0));
+        assert!(!holds(&downloads[0], 1, 7));
+    }
+
+    #[test]
+    fn test_move_in() {
+        let mut downloads: Vec<_> = (1..=4).map(|id| queued(id, Priority::Normal)).collect();
+        let ids =
+            |downloads: &[DownloadItem]| downloads.iter().map(|item| item.id).collect::<Vec<_>>();
+        assert!(move_in(&mut downloads, 1, 2));
+        assert_eq!(ids(&downloads), vec![2, 3, 1, 4]);
+        assert!(move_in(&mut downloads, 4, -1));
+        assert_eq!(ids(&downloads), vec![2, 3, 4, 1]);
+        assert!(move_in(&mut downloads, 3, -5));
+        assert_eq!(ids(&downloads), vec![3, 2, 4, 1]);
+        assert!(!move_in(&mut downloads, 1, 1));
+        assert!(!move_in(&mut downloads, 9, 1));
+    }
+
+    #[test]
+    fn test_restored_queue_starts_over() {
+        let mut running = queued(7, Priority::High);
+        running.status = DownloadStatus::Running;
+        running.speed = 1000.0;
+        let mut paused = queued(7, Priority::Low);
+        paused.status = DownloadStatus::Paused;
+
+        let json = serde_json::to_string(&vec![running, paused]).unwrap();
+        let pending: Vec<DownloadItem> = serde_json::from_str(&json).unwrap();
+        let restored = restored(pending);
+        assert_eq!(restored[0].status, DownloadStatus::Queued);
+        assert_eq!(restored[0].priority, Priority::High);
+        assert_eq!(restored[0].speed, 0.0);
+        assert_eq!(restored[1].status, DownloadStatus::Paused);
+        assert_ne!(restored[0].id, restored[1].id);
+    }
+
+    // answers the n-th request for the video with the n-th response, the
+    // last one is repeated; returns the url and the requests per path
+    fn serve(responses: Vec<Response>) -> (String, Arc<Mutex<HashMap<String, usize>>>) {
+        let (base, requests) = testing::serve(move |request| {
+            responses[(request.count - 1).min(responses.len() - 1)].clone()
+        });
+        (format!("{base}/video.mp4"), requests)
+    }
+
+    fn requests_of(requests: &Mutex<HashMap<String, usize>>) -> usize {
+        requests.lock().unwrap()["/video.mp4"]
+    }
+
+    fn policy(attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            attempts,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(5),
+        }
+    }
+
+    // downloads into a fresh temporary folder and returns the result and
+    // the attempts announced to `on_retry`
+    fn download(
+        url: &str,
+        attempts: u32,
+        name: &str,
+    ) -> (Result<u64, DownloadError>, Vec<u32>, PathBuf) {
+        let directory =
+            std::env::temp_dir().join(format!("medow-retry-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        let destination = directory.join("video.mp4");
+        let mut retries = Vec::new();
+        let result = block_on(fetch_with_retry(
+            &reqwest::Client::new(),
+            url,
+            &destination,
+            None,
+            policy(attempts),
+            |_| {},
+            |attempt, _| retries.push(attempt),
+        ));
+        (result, retries, destination)
+    }
+
+    #[test]
+    fn test_retry_delay_grows_with_jitter() {
+        let policy = RetryPolicy {
+            attempts: 5,
+            base_delay: Duration::from_secs(2),
+            max_delay: Duration::from_secs(60),
+        };
+        assert_eq!(policy.delay(2, 1.0), Duration::from_secs(2));
+        assert_eq!(policy.delay(2, 0.0), Duration::from_secs(1));
+        assert_eq!(policy.delay(4, 1.0), Duration::from_secs(8));
+        assert_eq!(policy.delay(30, 1.0), Duration::from_secs(60));
+        let jitter = jitter();
+        assert!((0.0..1.0).contains(&jitter));
+    }
+
+    #[test]
+    fn test_transient_errors() {
+        let incomplete = DownloadError::Incomplete {
+            expected: 10,
+            received: 5,
+        };
+        assert!(incomplete.is_transient());
+        assert!(DownloadError::Hls(HlsError::Interrupted).is_transient());
+        assert!(!DownloadError::MissingUrl.is_transient());
+        assert!(!DownloadError::Io(std::io::Error::other("disk full")).is_transient());
+    }
+
+    #[test]
+    fn test_server_errors_are_retried() {
+        let (url, requests) = serve(vec![
+            Response::status("503 Service Unavailable"),
+            Response::ok("video"),
+        ]);
+        let (result, retries, destination) = download(&url, 3, "server-error");
+        assert_eq!(result.unwrap(), 5);
+        assert_eq!(retries, vec![2]);
+        assert_eq!(requests_of(&requests), 2);
+        assert_eq!(std::fs::read(&destination).unwrap(), b"video");
+        std::fs::remove_dir_all(destination.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_gives_up_after_all_attempts() {
+        let (url, requests) = serve(vec![Response::status("500 Internal Server Error")]);
+        let (result, retries, destination) = download(&url, 3, "exhausted");
+        assert!(matches!(
+            result,
+            Err(DownloadError::Exhausted { attempts: 3, .. })
+        ));
+        assert_eq!(retries, vec![2, 3]);
+        assert_eq!(requests_of(&requests), 3);
+        assert!(!destination.exists());
+        let _ = std::fs::remove_dir_all(destination.parent().unwrap());
+    }
+
+    #[test]
+    fn test_client_errors_are_not_retried() {
+        let (url, requests) = serve(vec![Response::status("404 Not Found")]);
+        let (result, retries, destination) = download(&url, 3, "not-found");
+        assert!(matches!(result, Err(DownloadError::Http(_))));
+        assert!(retries.is_empty());
+        assert_eq!(requests_of(&requests), 1);
+        let _ = std::fs::remove_dir_all(destination.parent().unwrap());
+    }
+
+    #[test]
+    fn test_resumed_file_shorter_than_announced_is_incomplete() {
+        // the server resumes at the right offset but tells a larger full size
+        // than it sends, so the finished part file falls short
+        let (url, _) = serve(vec![
+            Response::ok(vec![0; 20])
+                .with_header("Accept-Ranges", "bytes")
+                .with_header("ETag", "\"v1\""),
+            Response::ok("deo.mp4")
+                .with_status("206 Partial Content")
+                .with_header("Content-Range", "bytes 3-9/20"),
+        ]);
+        let directory =
+            std::env::temp_dir().join(format!("medow-incomplete-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        let destination = directory.join("video.mp4");
+        std::fs::write(resume::part_path(&destination), "vid").unwrap();
+        let meta = PartialMeta {
+            url: url.clone(),
+            etag: Some(String::from("\"v1\"")),
+            last_modified: None,
+            total: Some(20),
+        };
+        block_on(resume::save_meta(&destination, &meta)).unwrap();
+
+        let client = reqwest::Client::new();
+        let result = block_on(fetch_to_file(&client, &url, &destination, |_| {}));
+        assert!(matches!(
+            result,
+            Err(DownloadError::Incomplete {
+                expected: 20,
+                received: 10
+            })
+        ));
+        assert!(!destination.exists());
+        // kept for the next attempt
+        let part = std::fs::read(resume::part_path(&destination)).unwrap();
+        assert_eq!(part, b"viddeo.mp4");
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_truncated_file_is_not_finished() {
+        // the connection closes after 5 of the announced 10 bytes
+        let (url, _) = serve(vec![Response::ok("video").with_length(10)]);
+        let (result, _, destination) = download(&url, 1, "truncated");
+        assert!(result.unwrap_err().is_transient());
+        assert!(!destination.exists());
+        std::fs::remove_dir_all(destination.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,192 @@
+use crate::{
+    pagination::SearchItem,
+    storage::{self, StorageError},
+};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+const LOG_FILE: &str = "downloads.jsonl";
+
+/// What identifies an episode, independent of the quality it is fetched in
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Episode {
+    pub id: String,
+    pub channel: String,
+    pub topic: String,
+    pub title: String,
+    /// unix timestamp of the broadcast
+    pub date: i64,
+    /// video urls of all qualities
+    pub urls: Vec<String>,
+}
+
+impl Episode {
+    pub fn from_item(item: &SearchItem) -> Self {
+        let mut urls: Vec<String> = item
+            .video_urls
+            .available()
+            .into_iter()
+            .filter_map(|quality| item.video_urls.get(quality).cloned())
+            .collect();
+        if !item.video_url.is_empty() && !urls.contains(&item.video_url) {
+            urls.push(item.video_url.clone());
+        }
+        Episode {
+            id: item.id.clone(),
+            channel: item.channel.clone(),
+            topic: item.topic.clone(),
+            title: item.title.clone(),
+            date: item.published,
+            urls,
+        }
+    }
+
+    /// Checks whether both are the same episode: the same entry, a shared
+    /// video url or the same broadcast listed again with other urls
+    pub fn is_same(&self, other: &Episode) -> bool {
+        let same_id = !self.id.is_empty() && self.id == other.id;
+        let same_url = self.urls.iter().any(|url| other.urls.contains(url));
+        let same_broadcast = self.date != 0
+            && self.date == other.date
+            && !self.title.is_empty()
+            && self.title.eq_ignore_ascii_case(&other.title)
+            && self.topic.eq_ignore_ascii_case(&other.topic)
+            && self.channel.eq_ignore_ascii_case(&other.channel);
+        same_id || same_url || same_broadcast
+    }
+}
+
+/// Outcome of a download
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordStatus {
+    Finished,
+    Failed(String),
+}
+
+/// A finished or failed download as stored in the download history
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DownloadRecord {
+    #[serde(flatten)]
+    pub episode: Episode,
+    /// the url that was downloaded
+    pub url: String,
+    pub destination: PathBuf,
+    /// bytes written
+    pub size: Option<u64>,
+    pub status: RecordStatus,
+    /// unix timestamp of the end of the download
+    pub recorded: i64,
+}
+
+/// All downloads ever made, kept as an append-only json log in the data directory
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DownloadLog {
+    pub records: Vec<DownloadRecord>,
+}
+
+impl DownloadLog {
+    pub fn load() -> Result<DownloadLog, StorageError> {
+        storage::load_json_lines(LOG_FILE).map(|records| DownloadLog { records })
+    }
+
+    /// Appends a record to the log file
+    pub fn write(record: &DownloadRecord) -> Result<(), StorageError> {
+        storage::append_json_line(LOG_FILE, record)
+    }
+
+    /// Remembers a download, keeping it in memory even if writing the file fails
+    pub fn record(&mut self, record: DownloadRecord) -> Result<(), StorageError> {
+        let written = Self::write(&record);
+        self.records.push(record);
+        written
+    }
+
+    /// Gets the last finished download of the same episode
+    pub fn finished(&self, episode: &Episode) -> Option<&DownloadRecord> {
+        self.records.iter().rev().find(|record| {
+            record.status == RecordStatus::Finished && record.episode.is_same(episode)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pagination::VideoUrls;
+
+    fn item(id: &str) -> SearchItem {
+        SearchItem {
+            id: String::from(id),
+            channel: String::from("ARD"),
+            topic: String::from("Tatort"),
+            title: String::from("Der Fall Holdt"),
+            published: 1_700_000_000,
+            video_url: format!("https://example.org/{id}_sd.mp4"),
+            video_urls: VideoUrls {
+                hd: Some(format!("https://example.org/{id}_hd.mp4")),
+                sd: Some(format!("https://example.org/{id}_sd.mp4")),
+                low: None,
+            },
+            ..Default::default()
+        }
+    }
+
+    fn record(episode: Episode, status: RecordStatus) -> DownloadRecord {
+        DownloadRecord {
+            url: episode.urls[0].clone(),
+            episode,
+            destination: PathBuf::from("/srv/media/tatort.mp4"),
+            size: Some(1024),
+            status,
+            recorded: 1_700_000_100,
+        }
+    }
+
+    #[test]
+    fn test_same_episode_in_other_quality() {
+        let sd = Episode::from_item(&item("a"));
+        let mut hd = item("");
+        hd.title = String::from("other");
+        hd.video_url = String::from("https://example.org/a_hd.mp4");
+        hd.video_urls = VideoUrls::default();
+        assert!(sd.is_same(&Episode::from_item(&hd)));
+    }
+
+    #[test]
+    fn test_same_broadcast_with_other_id() {
+        let first = Episode::from_item(&item("a"));
+        let mut second = item("b");
+        second.title = String::from("der fall holdt");
+        assert!(first.is_same(&Episode::from_item(&second)));
+        second.published += 3600;
+        assert!(!first.is_same(&Episode::from_item(&second)));
+    }
+
+    #[test]
+    fn test_finished_ignores_failed_downloads() {
+        let episode = Episode::from_item(&item("a"));
+        let mut log = DownloadLog::default();
+        log.records.push(record(
+            episode.clone(),
+            RecordStatus::Failed(String::from("timeout")),
+        ));
+        assert!(log.finished(&episode).is_none());
+        log.records
+            .push(record(episode.clone(), RecordStatus::Finished));
+        assert!(log.finished(&episode).is_some());
+    }
+
+    #[test]
+    fn test_record_json() {
+        let record = record(Episode::from_item(&item("a")), RecordStatus::Finished);
+        let json = serde_json::to_string(&record).unwrap();
+        assert!(json.contains("\"status\":\"finished\""));
+        assert!(json.contains("\"title\":\"Der Fall Holdt\""));
+        assert_eq!(
+            serde_json::from_str::<DownloadRecord>(&json).unwrap(),
+            record
+        );
+    }
+}
@@ -0,0 +1,245 @@
+use crate::{
+    download::{self, DownloadItem, DownloadStatus, Priority},
+    utils, View, APP_STATE, DOWNLOADS,
+};
+use dioxus::prelude::*;
+
+#[component]
+fn download_header() -> Element {
+    rsx! {
+        header {
+            class: "sticky-header",
+            article {
+                padding_bottom: 0,
+                padding_top: 0,
+                nav {
+                    ul {
+                        li { strong { "Downloads" } }
+                    }
+                    ul {
+                        li {
+                            button {
+                                class: "button",
+                                onclick: move |_| APP_STATE.write().view = View::Search,
+                                "Search",
+                            }
+                        }
+                        li {
+                            button {
+                                class: "button",
+                                onclick: move |_| APP_STATE.write().view = View::Subscriptions,
+                                "Subscriptions",
+                            }
+                        }
+                        li {
+                            button {
+                                class: "button",
+                                onclick: move |_| APP_STATE.write().view = View::Settings,
+                                "Settings",
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[component]
+fn download_row(item: DownloadItem) -> Element {
+    let attempts = APP_STATE.read().settings.download_attempts;
+    let status = match &item.status {
+        DownloadStatus::Queued => String::from("queued"),
+        DownloadStatus::Running if item.attempt > 1 => {
+            format!("retrying, attempt {} of {attempts}", item.attempt)
+        }
+        DownloadStatus::Running => String::from("running"),
+        DownloadStatus::Paused => String::from("paused"),
+        DownloadStatus::Finished => String::from("finished"),
+        DownloadStatus::Failed(reason) => format!("failed: {reason}"),
+        DownloadStatus::Cancelled => String::from("cancelled"),
+    };
+    let size = match item.total {
+        Some(total) => format!(
+            "{} / {}",
+            utils::format_bytes(item.downloaded),
+            utils::format_bytes(total)
+        ),
+        None => utils::format_bytes(item.downloaded),
+    };
+    let id = item.id;
+    let running = item.status == DownloadStatus::Running;
+    let pausable = matches!(
+        item.status,
+        DownloadStatus::Queued | DownloadStatus::Running
+    );
+    let retryable = matches!(
+        item.status,
+        DownloadStatus::Failed(_) | DownloadStatus::Cancelled
+    );
+    let done = matches!(
+        item.status,
+        DownloadStatus::Finished | DownloadStatus::Cancelled
+    );
+
+    rsx! {
+        tr {
+            td {
+                class: "queue-order",
+                button {
+                    class: "button outline secondary",
+                    title: "Move up",
+                    onclick: move |_| download::move_by(id, -1),
+                    "▲",
+                }
+                button {
+                    class: "button outline secondary",
+                    title: "Move down",
+                    onclick: move |_| download::move_by(id, 1),
+                    "▼",
+                }
+            }
+            td { "{item.title}" }
+            td { "{item.topic}" }
+            td {
+                match item.percent() {
+                    Some(percent) => rsx! { progress { value: percent, max: 100 } },
+                    None if running => rsx! { progress {} },
+                    None => rsx! {},
+                }
+            }
+            td { "{size}" }
+            td {
+                if running {
+                    "{utils::format_speed(item.speed)}"
+                }
+            }
+            td {
+                if running {
+                    "{utils::format_eta(item.eta())}"
+                }
+            }
+            td {
+                select {
+                    title: "Priority",
+                    disabled: done || running,
+                    onchange: move |event_data| {
+                        if let Some(priority) = Priority::from_label(&event_data.value()) {
+                            download::set_priority(id, priority);
+                        }
+                    },
+                    for priority in Priority::ALL {
+                        option {
+                            value: "{priority}",
+                            selected: item.priority == priority,
+                            "{priority}"
+                        }
+                    }
+                }
+            }
+            td { "{status}" }
+            td {
+                class: "queue-actions",
+                if pausable {
+                    button {
+                        class: "button outline",
+                        onclick: move |_| download::pause(id),
+                        "Pause",
+                    }
+                }
+                if item.status == DownloadStatus::Paused {
+                    button {
+                        class: "button outline",
+                        onclick: move |_| download::resume(id),
+                        "Resume",
+                    }
+                }
+                if retryable {
+                    button {
+                        class: "button outline",
+                        onclick: move |_| download::retry(id),
+                        "Retry",
+                    }
+                }
+                if !done {
+                    button {
+                        class: "button outline secondary",
+                        title: "Cancel download",
+                        onclick: move |_| download::cancel(id),
+                        "✕",
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[component]
+pub fn download_view() -> Element {
+    let limit = APP_STATE.read().settings.max_concurrent_downloads;
+    let window = APP_STATE.read().settings.download_window.clone();
+    let waiting = !download::window_open()
+        && DOWNLOADS
+            .read()
+            .iter()
+            .any(|item| item.status == DownloadStatus::Queued);
+    let has_done = DOWNLOADS.read().iter().any(|item| {
+        matches!(
+            item.status,
+            DownloadStatus::Finished | DownloadStatus::Cancelled
+        )
+    });
+
+    rsx! {
+        download_header {}
+        main {
+            article {
+                padding_bottom: 0,
+                padding_top: 5,
+                if DOWNLOADS.read().is_empty() {
+                    p { "No downloads yet. Select media in the search view and press \"Download\"." }
+                } else {
+                    nav {
+                        ul {
+                            li { small { "Up to {limit} downloads run at the same time, higher priorities first." } }
+                            if waiting {
+                                li { small { "Waiting for the download window {window.label()}." } }
+                            }
+                        }
+                        ul {
+                            li {
+                                button {
+                                    class: "button outline",
+                                    disabled: !has_done,
+                                    onclick: move |_| download::clear_done(),
+                                    "Clear finished",
+                                }
+                            }
+                        }
+                    }
+                    table {
+                        thead {
+                            tr {
+                                th { scope: "col" }
+                                th { scope: "col", "title" }
+                                th { scope: "col", "topic" }
+                                th { scope: "col", "progress" }
+                                th { scope: "col", "size" }
+                                th { scope: "col", "speed" }
+                                th { scope: "col", "eta" }
+                                th { scope: "col", "priority" }
+                                th { scope: "col", "status" }
+                                th { scope: "col" }
+                            }
+                        }
+                        tbody {
+                            for item in DOWNLOADS.read().iter() {
+                                download_row { key: "{item.id}", item: item.clone() }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,257 @@
+use crate::{
+    disk::SpaceError, download::DownloadError, filename::TemplateError, filmliste::FilmlisteError,
+    schedule::ScheduleError, search_backend::BackendError, settings::SettingsError,
+    storage::StorageError, subtitles::SubtitleError, APP_STATE,
+};
+use std::rc::Rc;
+
+/// Errors shown to the user, grouped by what went wrong
+#[derive(Debug, thiserror::Error)]
+pub enum AppError {
+    #[error("Network error: {0}")]
+    Network(String),
+    #[error("MediathekViewWeb error: {0}")]
+    Api(String),
+    #[error("Invalid data: {0}")]
+    Parse(String),
+    #[error("File error: {0}")]
+    Filesystem(String),
+    #[error("Configuration error: {0}")]
+    Config(String),
+}
+
+impl From<mediathekviewweb::Error> for AppError {
+    fn from(error: mediathekviewweb::Error) -> Self {
+        match error {
+            mediathekviewweb::Error::Reqwest(error) => AppError::Network(error.to_string()),
+            mediathekviewweb::Error::EmptyResponse => {
+                AppError::Api(String::from("the server returned an empty response"))
+            }
+            mediathekviewweb::Error::Response(error) => AppError::Api(error.0.join(", ")),
+        }
+    }
+}
+
+impl From<BackendError> for AppError {
+    fn from(error: BackendError) -> Self {
+        match error {
+            BackendError::Api(error) => error.into(),
+            BackendError::Filmliste(error) => error.into(),
+        }
+    }
+}
+
+impl From<FilmlisteError> for AppError {
+    fn from(error: FilmlisteError) -> Self {
+        match error {
+            FilmlisteError::NoIndex => AppError::Config(error.to_string()),
+            FilmlisteError::Json(_) => AppError::Parse(error.to_string()),
+            FilmlisteError::Storage(error) => error.into(),
+            FilmlisteError::Io(..) | FilmlisteError::Interrupted => {
+                AppError::Filesystem(error.to_string())
+            }
+        }
+    }
+}
+
+impl From<StorageError> for AppError {
+    fn from(error: StorageError) -> Self {
+        match error {
+            StorageError::NoDataDir => AppError::Config(error.to_string()),
+            StorageError::Io(..) => AppError::Filesystem(error.to_string()),
+            StorageError::Json(..) | StorageError::BackedUp(..) => {
+                AppError::Parse(error.to_string())
+            }
+        }
+    }
+}
+
+impl From<SettingsError> for AppError {
+    fn from(error: SettingsError) -> Self {
+        match error {
+            SettingsError::Io(_) => AppError::Filesystem(error.to_string()),
+            _ => AppError::Config(error.to_string()),
+        }
+    }
+}
+
+impl From<TemplateError> for AppError {
+    fn from(error: TemplateError) -> Self {
+        AppError::Config(format!("invalid filename template: {error}"))
+    }
+}
+
+impl From<ScheduleError> for AppError {
+    fn from(error: ScheduleError) -> Self {
+        AppError::Config(format!("invalid download window: {error}"))
+    }
+}
+
+impl From<DownloadError> for AppError {
+    fn from(error: DownloadError) -> Self {
+        match error {
+            DownloadError::MissingUrl => AppError::Api(error.to_string()),
+            DownloadError::Http(error) => AppError::Network(error.to_string()),
+            DownloadError::Io(_) => AppError::Filesystem(error.to_string()),
+            DownloadError::Hls(_) => AppError::Parse(error.to_string()),
+            // only transient failures are retried until they are exhausted
+            DownloadError::Incomplete { .. } | DownloadError::Exhausted { .. } => {
+                AppError::Network(error.to_string())
+            }
+        }
+    }
+}
+
+impl From<SpaceError> for AppError {
+    fn from(error: SpaceError) -> Self {
+        AppError::Filesystem(error.to_string())
+    }
+}
+
+impl From<SubtitleError> for AppError {
+    fn from(error: SubtitleError) -> Self {
+        match error {
+            SubtitleError::Http(error) => AppError::Network(error.to_string()),
+            SubtitleError::Io(_) => AppError::Filesystem(error.to_string()),
+            SubtitleError::Xml(_) | SubtitleError::Empty => AppError::Parse(error.to_string()),
+        }
+    }
+}
+
+/// The error shown in the banner together with a way to repeat what failed
+pub struct ErrorReport {
+    pub error: AppError,
+    pub retry: Option<Rc<dyn Fn()>>,
+}
+
+impl ErrorReport {
+    pub fn new(error: impl Into<AppError>) -> Self {
+        ErrorReport {
+            error: error.into(),
+            retry: None,
+        }
+    }
+
+    pub fn with_retry(error: impl Into<AppError>, retry: impl Fn() + 'static) -> Self {
+        ErrorReport {
+            error: error.into(),
+            retry: Some(Rc::new(retry)),
+        }
+    }
+}
+
+/// Shows an error that can't be fixed by trying again
+pub fn report(error: impl Into<AppError>) {
+    queue(&mut APP_STATE.write().errors, ErrorReport::new(error));
+}
+
+/// Shows an error and offers to run `retry` again
+pub fn report_with_retry(error: impl Into<AppError>, retry: impl Fn() + 'static) {
+    queue(
+        &mut APP_STATE.write().errors,
+        ErrorReport::with_retry(error, retry),
+    );
+}
+
+/// Hides the shown error, the next one in the queue takes its place
+pub fn dismiss() {
+    take_first(&mut APP_STATE.write().errors);
+}
+
+/// Hides the shown error and repeats the operation that failed
+pub fn retry() {
+    let report = take_first(&mut APP_STATE.write().errors);
+    if let Some(retry) = report.and_then(|report| report.retry) {
+        retry();
+    }
+}
+
+// adds a report to the queue, an older report of the same error is replaced
+fn queue(errors: &mut Vec<ErrorReport>, report: ErrorReport) {
+    let message = report.error.to_string();
+    errors.retain(|queued| queued.error.to_string() != message);
+    errors.push(report);
+}
+
+fn take_first(errors: &mut Vec<ErrorReport>) -> Option<ErrorReport> {
+    (!errors.is_empty()).then(|| errors.remove(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_api_errors() {
+        let error = AppError::from(BackendError::Api(mediathekviewweb::Error::EmptyResponse));
+        assert!(matches!(error, AppError::Api(_)));
+        assert_eq!(
+            error.to_string(),
+            "MediathekViewWeb error: the server returned an empty response"
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let json = serde_json::from_str::<u32>("x").unwrap_err();
+        let error = AppError::from(StorageError::Json(PathBuf::from("history.json"), json));
+        assert!(matches!(error, AppError::Parse(_)));
+        assert!(matches!(
+            AppError::from(SubtitleError::Empty),
+            AppError::Parse(_)
+        ));
+    }
+
+    #[test]
+    fn test_filesystem_errors() {
+        let io = std::io::Error::from(std::io::ErrorKind::PermissionDenied);
+        let error = AppError::from(FilmlisteError::Io(PathBuf::from("/srv/list.xz"), io));
+        assert!(matches!(error, AppError::Filesystem(_)));
+        assert!(error.to_string().contains("/srv/list.xz"));
+        let error = AppError::from(SpaceError::Insufficient {
+            path: PathBuf::from("/srv/media"),
+            needed: 2048,
+            available: 1024,
+        });
+        assert!(matches!(error, AppError::Filesystem(_)));
+    }
+
+    #[test]
+    fn test_queue_keeps_every_error() {
+        let mut errors = Vec::new();
+        queue(&mut errors, ErrorReport::new(SettingsError::NoConfigDir));
+        queue(&mut errors, ErrorReport::new(FilmlisteError::NoIndex));
+        queue(
+            &mut errors,
+            ErrorReport::with_retry(SettingsError::NoConfigDir, || {}),
+        );
+        assert_eq!(errors.len(), 2);
+        assert!(errors[1].retry.is_some());
+
+        let first = take_first(&mut errors).unwrap();
+        assert!(first.error.to_string().contains("film list"));
+        assert!(take_first(&mut errors).is_some());
+        assert!(take_first(&mut errors).is_none());
+    }
+
+    #[test]
+    fn test_config_errors() {
+        assert!(matches!(
+            AppError::from(SettingsError::NoConfigDir),
+            AppError::Config(_)
+        ));
+        assert!(matches!(
+            AppError::from(FilmlisteError::NoIndex),
+            AppError::Config(_)
+        ));
+        assert_eq!(
+            AppError::from(TemplateError::Empty).to_string(),
+            "Configuration error: invalid filename template: template results in an empty filename"
+        );
+        assert_eq!(
+            AppError::from(ScheduleError::InvalidTime(String::from("25:00"))).to_string(),
+            "Configuration error: invalid download window: invalid time \"25:00\", expected HH:MM"
+        );
+    }
+}
@@ -0,0 +1,43 @@
+use crate::{error, APP_STATE};
+use dioxus::prelude::*;
+
+/// Shows the oldest error with buttons to retry the failed operation or dismiss it,
+/// the others follow one by one.
+///
+/// Stays mounted while there is no error, so tasks started by a retry aren't
+/// dropped together with the banner.
+#[component]
+pub fn error_banner() -> Element {
+    let (message, can_retry, queued) = match APP_STATE.read().errors.as_slice() {
+        [report, queued @ ..] => (
+            report.error.to_string(),
+            report.retry.is_some(),
+            queued.len(),
+        ),
+        [] => return rsx! {},
+    };
+
+    rsx! {
+        div {
+            class: "error-banner",
+            role: "alert",
+            span { "{message}" }
+            if queued > 0 {
+                span { class: "error-count", "+{queued} more" }
+            }
+            if can_retry {
+                button {
+                    class: "button outline",
+                    onclick: move |_| error::retry(),
+                    "Retry",
+                }
+            }
+            button {
+                class: "button outline secondary",
+                title: "Dismiss",
+                onclick: move |_| error::dismiss(),
+                "✕",
+            }
+        }
+    }
+}
@@ -0,0 +1,361 @@
+use crate::pagination::SearchItem;
+use std::path::{Path, PathBuf};
+
+/// Template used when the settings don't define one
+pub const DEFAULT_TEMPLATE: &str = "{index:02} - {title}.{ext}";
+
+// most filesystems limit a single path component to 255 bytes
+const MAX_COMPONENT_BYTES: usize = 255;
+
+// characters that are not allowed in filenames on windows, macOS or linux
+const ILLEGAL_CHARS: [char; 9] = ['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+
+// names windows refuses to use for files, regardless of the extension
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum TemplateError {
+    #[error("unknown placeholder {{{0}}}")]
+    UnknownPlaceholder(String),
+    #[error("invalid format \"{1}\" for placeholder {{{0}}}")]
+    InvalidFormat(String, String),
+    #[error("unclosed placeholder starting at position {0}")]
+    Unclosed(usize),
+    #[error("unexpected '}}' at position {0}")]
+    UnexpectedClose(usize),
+    #[error("template results in an empty filename")]
+    Empty,
+}
+
+/// Values available to a filename template
+pub struct TemplateContext<'a> {
+    /// 1-based position of the item in the batch being queued
+    pub index: usize,
+    pub item: &'a SearchItem,
+    /// file extension without the leading dot
+    pub ext: &'a str,
+}
+
+/// Renders a template like `{index:02} - {topic} - {title} ({date}).{ext}`
+/// into a relative path.
+///
+/// Placeholders are `index`, `channel`, `title`, `topic`, `date`, `duration`,
+/// `quality` and `ext`. Numbers accept a zero padded width (`{index:03}`), text a
+/// maximum length (`{title:40}`). `{{` and `}}` produce literal braces and a
+/// `/` in the template creates a subfolder. Substituted values are sanitized,
+/// every path component is truncated to 255 bytes.
+pub fn render(template: &str, context: &TemplateContext) -> Result<PathBuf, TemplateError> {
+    let mut rendered = String::new();
+    let mut chars = template.char_indices().peekable();
+    while let Some((position, c)) = chars.next() {
+        match c {
+            '{' if chars.peek().map(|(_, next)| *next) == Some('{') => {
+                chars.next();
+                rendered.push('{');
+            }
+            '}' if chars.peek().map(|(_, next)| *next) == Some('}') => {
+                chars.next();
+                rendered.push('}');
+            }
+            '{' => {
+                let mut placeholder = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '}')) => break,
+                        Some((_, c)) => placeholder.push(c),
+                        None => return Err(TemplateError::Unclosed(position)),
+                    }
+                }
+                rendered.push_str(&substitute(&placeholder, context)?);
+            }
+            '}' => return Err(TemplateError::UnexpectedClose(position)),
+            c => rendered.push(c),
+        }
+    }
+
+    let path: PathBuf = rendered
+        .split('/')
+        .map(|component| truncate(component.trim(), MAX_COMPONENT_BYTES))
+        .filter(|component| !matches!(component.as_str(), "" | "." | ".."))
+        .collect();
+    if path.as_os_str().is_empty() {
+        return Err(TemplateError::Empty);
+    }
+    Ok(path)
+}
+
+// resolves a single `name[:format]` placeholder
+fn substitute(placeholder: &str, context: &TemplateContext) -> Result<String, TemplateError> {
+    let (name, format) = match placeholder.split_once(':') {
+        Some((name, format)) => (name.trim(), Some(format.trim())),
+        None => (placeholder.trim(), None),
+    };
+    let invalid_format =
+        || TemplateError::InvalidFormat(name.to_string(), format.unwrap_or_default().to_string());
+
+    if name == "index" {
+        return match format {
+            None => Ok(context.index.to_string()),
+            Some(width) => {
+                let width: usize = width.parse().map_err(|_| invalid_format())?;
+                Ok(format!("{:0width$}", context.index, width = width))
+            }
+        };
+    }
+
+    let value = match name {
+        "channel" => &context.item.channel,
+        "title" => &context.item.title,
+        "topic" => &context.item.topic,
+        "date" => &context.item.timestamp,
+        "duration" => &context.item.duration,
+        "quality" => &context.item.quality,
+        "ext" => context.ext,
+        _ => return Err(TemplateError::UnknownPlaceholder(name.to_string())),
+    };
+    let value = sanitize(value);
+    match format {
+        None => Ok(value),
+        Some(max_length) => {
+            let max_length: usize = max_length.parse().map_err(|_| invalid_format())?;
+            Ok(value
+                .chars()
+                .take(max_length)
+                .collect::<String>()
+                .trim_end()
+                .to_string())
+        }
+    }
+}
+
+/// Replaces characters that are illegal on common filesystems,
+/// collapses whitespace and avoids names reserved by windows
+pub fn sanitize(value: &str) -> String {
+    let replaced: String = value
+        .chars()
+        .map(|c| {
+            if c.is_whitespace() {
+                ' '
+            } else if ILLEGAL_CHARS.contains(&c) || c.is_control() {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+    let collapsed = replaced.split_whitespace().collect::<Vec<_>>().join(" ");
+    // windows silently drops trailing dots and spaces
+    let trimmed = collapsed.trim_end_matches(['.', ' ']).to_string();
+
+    let stem = trimmed.split('.').next().unwrap_or_default();
+    if RESERVED_NAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(stem))
+    {
+        format!("_{trimmed}")
+    } else {
+        trimmed
+    }
+}
+
+/// Shortens a filename to at most `max_bytes`, keeping the extension
+/// and never splitting a multi-byte character
+pub fn truncate(name: &str, max_bytes: usize) -> String {
+    if name.len() <= max_bytes {
+        return name.to_string();
+    }
+    let (stem, ext) = match name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() && ext.len() < max_bytes / 2 => {
+            (stem, format!(".{ext}"))
+        }
+        _ => (name, String::new()),
+    };
+    let mut end = max_bytes - ext.len();
+    while !stem.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}{}", stem[..end].trim_end(), ext)
+}
+
+/// Appends ` (2)`, ` (3)`, ... to the filename until `is_taken` accepts it
+pub fn unique_path(path: &Path, is_taken: impl Fn(&Path) -> bool) -> PathBuf {
+    if !is_taken(path) {
+        return path.to_path_buf();
+    }
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let ext = path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    (2..)
+        .map(|counter| {
+            let suffix = format!(" ({counter}){ext}");
+            path.with_file_name(format!(
+                "{}{}",
+                truncate(&stem, MAX_COMPONENT_BYTES - suffix.len()),
+                suffix
+            ))
+        })
+        .find(|candidate| !is_taken(candidate))
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+/// Gets the file extension from the last path segment of a url,
+/// assuming mp4 if there is none. HLS playlists are saved as transport stream.
+pub fn extension_from_url(url: &str) -> String {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    let segment = path.rsplit('/').next().unwrap_or_default();
+    match segment.rsplit_once('.') {
+        Some((stem, ext))
+            if !stem.is_empty() && !ext.is_empty() && ext.chars().all(char::is_alphanumeric) =>
+        {
+            match ext.to_lowercase().as_str() {
+                "m3u8" => String::from("ts"),
+                ext => ext.to_string(),
+            }
+        }
+        _ => String::from("mp4"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pagination::VideoUrls;
+
+    fn item() -> SearchItem {
+        SearchItem {
+            channel: String::from("ARD"),
+            title: String::from("Der Fall: Teil 1/2"),
+            topic: String::from("Tatort"),
+            timestamp: String::from("01.02.2024"),
+            duration: String::from("1:29"),
+            quality: String::from("HD"),
+            video_url: String::from("https://example.org/tatort.mp4"),
+            video_urls: VideoUrls {
+                hd: Some(String::from("https://example.org/tatort.mp4")),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn render_item(template: &str) -> Result<PathBuf, TemplateError> {
+        let item = item();
+        render(
+            template,
+            &TemplateContext {
+                index: 3,
+                item: &item,
+                ext: "mp4",
+            },
+        )
+    }
+
+    #[test]
+    fn test_render_full_template() {
+        assert_eq!(
+            render_item("{index:02} - {topic} - {title} ({date}).{ext}"),
+            Ok(PathBuf::from(
+                "03 - Tatort - Der Fall_ Teil 1_2 (01.02.2024).mp4"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_render_subfolder() {
+        assert_eq!(
+            render_item("{channel}/{topic}/{index} {title:8}.{ext}"),
+            Ok(PathBuf::from("ARD/Tatort/3 Der Fall.mp4"))
+        );
+    }
+
+    #[test]
+    fn test_render_escaped_braces() {
+        assert_eq!(
+            render_item("{{{quality}}}.{ext}"),
+            Ok(PathBuf::from("{HD}.mp4"))
+        );
+    }
+
+    #[test]
+    fn test_render_errors() {
+        assert_eq!(
+            render_item("{sender}"),
+            Err(TemplateError::UnknownPlaceholder(String::from("sender")))
+        );
+        assert_eq!(render_item("{title"), Err(TemplateError::Unclosed(0)));
+        assert_eq!(
+            render_item("title}"),
+            Err(TemplateError::UnexpectedClose(5))
+        );
+        assert_eq!(
+            render_item("{index:xx}"),
+            Err(TemplateError::InvalidFormat(
+                String::from("index"),
+                String::from("xx")
+            ))
+        );
+        assert_eq!(render_item(" / "), Err(TemplateError::Empty));
+        assert_eq!(render_item("../.."), Err(TemplateError::Empty));
+    }
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!(sanitize("a<b>c:d\"e|f?g*h\\i"), "a_b_c_d_e_f_g_h_i");
+        assert_eq!(sanitize("  lots   of\tspace  "), "lots of space");
+        assert_eq!(sanitize("trailing dots..."), "trailing dots");
+        assert_eq!(sanitize("con"), "_con");
+        assert_eq!(sanitize("NUL.mp4"), "_NUL.mp4");
+    }
+
+    #[test]
+    fn test_truncate_keeps_extension() {
+        let name = format!("{}.mp4", "a".repeat(300));
+        let truncated = truncate(&name, 255);
+        assert_eq!(truncated.len(), 255);
+        assert!(truncated.ends_with(".mp4"));
+    }
+
+    #[test]
+    fn test_truncate_multibyte() {
+        let truncated = truncate("äöü.mp4", 9);
+        assert_eq!(truncated, "äö.mp4");
+    }
+
+    #[test]
+    fn test_unique_path() {
+        let taken = [
+            PathBuf::from("/media/a.mp4"),
+            PathBuf::from("/media/a (2).mp4"),
+        ];
+        assert_eq!(
+            unique_path(Path::new("/media/a.mp4"), |path| taken
+                .iter()
+                .any(|taken| taken == path)),
+            PathBuf::from("/media/a (3).mp4")
+        );
+        assert_eq!(
+            unique_path(Path::new("/media/b.mp4"), |path| taken
+                .iter()
+                .any(|taken| taken == path)),
+            PathBuf::from("/media/b.mp4")
+        );
+    }
+
+    #[test]
+    fn test_extension_from_url() {
+        assert_eq!(
+            extension_from_url("https://example.org/clip.MP4?x=1"),
+            "mp4"
+        );
+        assert_eq!(extension_from_url("https://example.org/master.m3u8"), "ts");
+        assert_eq!(extension_from_url("https://example.org/stream/"), "mp4");
+    }
+}
@@ -0,0 +1,153 @@
+use crate::{download, hls, pagination::SearchItem, resume, FILE_SIZES};
+use dioxus::{core::spawn_forever, prelude::*};
+use std::time::Duration;
+use tokio::task::JoinSet;
+
+// number of HEAD requests sent at the same time
+const PROBE_CONCURRENCY: usize = 4;
+
+// servers that don't answer quickly leave the size unknown
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What is known about the size of a video file
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FileSize {
+    Probing,
+    Known(u64),
+    /// the server didn't tell, always the case for HLS streams
+    Unknown,
+}
+
+impl From<Option<u64>> for FileSize {
+    fn from(size: Option<u64>) -> Self {
+        size.map_or(FileSize::Unknown, FileSize::Known)
+    }
+}
+
+/// Asks the server for the size of the file behind `url` with a HEAD request
+pub async fn probe(client: &reqwest::Client, url: &str) -> Option<u64> {
+    if url.is_empty() || hls::is_playlist(url) {
+        return None;
+    }
+    let response = client
+        .head(url)
+        .timeout(PROBE_TIMEOUT)
+        .send()
+        .await
+        .ok()?
+        .error_for_status()
+        .ok()?;
+    // the body of a HEAD response is empty, so only the header tells the size
+    resume::RemoteInfo::from_headers(response.headers()).content_length
+}
+
+/// Probes the sizes of several urls, a few at a time, calling `on_size`
+/// as each answer arrives
+pub async fn probe_each(
+    client: &reqwest::Client,
+    urls: Vec<String>,
+    mut on_size: impl FnMut(String, Option<u64>),
+) {
+    let mut urls = urls.into_iter();
+    let mut running = JoinSet::new();
+    loop {
+        while running.len() < PROBE_CONCURRENCY {
+            let Some(url) = urls.next() else {
+                break;
+            };
+            let client = client.clone();
+            running.spawn(async move {
+                let size = probe(&client, &url).await;
+                (url, size)
+            });
+        }
+        match running.join_next().await {
+            Some(Ok((url, size))) => on_size(url, size),
+            Some(Err(_)) => {}
+            None => return,
+        }
+    }
+}
+
+/// Learns the sizes of the results in their selected quality in the
+/// background; every url is only asked for once
+pub fn probe_all(items: &[SearchItem]) {
+    let mut urls = Vec::new();
+    {
+        let mut sizes = FILE_SIZES.write();
+        for item in items {
+            if !sizes.contains_key(&item.video_url) {
+                sizes.insert(item.video_url.clone(), FileSize::Probing);
+                urls.push(item.video_url.clone());
+            }
+        }
+    }
+    if urls.is_empty() {
+        return;
+    }
+    // keeps probing when the view that asked for it is gone
+    spawn_forever(async move {
+        let Ok(client) = download::http_client() else {
+            let mut sizes = FILE_SIZES.write();
+            for url in urls {
+                sizes.insert(url, FileSize::Unknown);
+            }
+            return;
+        };
+        probe_each(&client, urls, |url, size| {
+            FILE_SIZES.write().insert(url, size.into());
+        })
+        .await;
+    });
+}
+
+/// Gets what is known about the size of a result in its selected quality
+pub fn size_of(item: &SearchItem) -> Option<FileSize> {
+    FILE_SIZES.read().get(&item.video_url).copied()
+}
+
+/// Gets the probed size of the file behind `url`, if it is known
+pub fn known(url: &str) -> Option<u64> {
+    match FILE_SIZES.read().get(url) {
+        Some(FileSize::Known(bytes)) => Some(*bytes),
+        _ => None,
+    }
+}
+
+/// Adds up known sizes, returning the sum and how many are still probed
+/// or unknown
+pub fn total(sizes: impl IntoIterator<Item = Option<FileSize>>) -> (u64, usize) {
+    sizes
+        .into_iter()
+        .fold((0, 0), |(sum, missing), size| match size {
+            Some(FileSize::Known(bytes)) => (sum + bytes, missing),
+            _ => (sum, missing + 1),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::block_on;
+
+    #[test]
+    fn test_total_counts_missing_sizes() {
+        let sizes = [
+            Some(FileSize::Known(100)),
+            Some(FileSize::Probing),
+            None,
+            Some(FileSize::Known(50)),
+            Some(FileSize::Unknown),
+        ];
+        assert_eq!(total(sizes), (150, 3));
+        assert_eq!(total([]), (0, 0));
+    }
+
+    #[test]
+    fn test_playlists_are_not_probed() {
+        let client = reqwest::Client::new();
+        // no request is sent, so the unroutable host doesn't matter
+        let size = block_on(probe(&client, "http://192.0.2.1/master.m3u8"));
+        assert_eq!(size, None);
+    }
+}
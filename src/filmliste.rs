@@ -0,0 +1,580 @@
+use crate::{
+    pagination::SearchItem,
+    query_parser::ParsedQuery,
+    search_backend::{self, BackendError, SearchBackend, SearchResults},
+    search_logic::{SearchOptions, SearchRequest},
+    storage::{self, StorageError},
+};
+use mediathekviewweb::models::{Item, SortField, SortOrder};
+use serde::{
+    de::{MapAccess, Visitor},
+    Deserialize, Deserializer, Serialize,
+};
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const INDEX_FILE: &str = "filmliste.json";
+
+// magic bytes at the start of every xz stream
+const XZ_MAGIC: [u8; 6] = [0xfd, b'7', b'z', b'X', b'Z', 0x00];
+
+// the index loaded from disk, shared by all searches
+static INDEX: Mutex<Option<Arc<FilmIndex>>> = Mutex::new(None);
+
+#[derive(Debug, thiserror::Error)]
+pub enum FilmlisteError {
+    #[error("no film list imported yet")]
+    NoIndex,
+    #[error("could not read {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("invalid film list: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+    #[error("loading the film list was interrupted")]
+    Interrupted,
+}
+
+/// Whether an imported list replaces the index or is merged into it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImportMode {
+    /// a complete film list (`Filmliste-akt.xz`)
+    Full,
+    /// the films added since the last complete list (`Filmliste-diff.xz`)
+    Diff,
+}
+
+/// A single entry of the film list
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Film {
+    pub id: String,
+    pub channel: String,
+    pub topic: String,
+    pub title: String,
+    pub description: Option<String>,
+    /// unix timestamp of the broadcast
+    pub timestamp: i64,
+    /// duration in seconds
+    pub duration: u64,
+    /// size in bytes
+    pub size: Option<u64>,
+    pub website: String,
+    pub subtitle: Option<String>,
+    pub video: String,
+    pub video_low: Option<String>,
+    pub video_hd: Option<String>,
+}
+
+impl Film {
+    // converts the entry into the format returned by the API
+    fn to_item(&self) -> Item {
+        Item {
+            channel: self.channel.clone(),
+            topic: self.topic.clone(),
+            title: self.title.clone(),
+            description: self.description.clone(),
+            timestamp: self.timestamp,
+            duration: Some(Duration::from_secs(self.duration)),
+            size: self.size.map(|size| size as usize),
+            url_website: self.website.clone(),
+            url_subtitle: self.subtitle.clone(),
+            url_video: self.video.clone(),
+            url_video_low: self.video_low.clone(),
+            url_video_hd: self.video_hd.clone(),
+            filmliste_timestamp: self.timestamp,
+            id: self.id.clone(),
+        }
+    }
+}
+
+/// The imported film list as stored in the data directory
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct FilmIndex {
+    /// creation date of the last imported list as given in its header
+    pub created: String,
+    pub films: Vec<Film>,
+}
+
+impl FilmIndex {
+    /// Answers a search like MediathekViewWeb would: selectors on the same
+    /// field are alternatives, different fields and plain words all have
+    /// to match. Films from the future are left out.
+    ///
+    /// Returns the number of all matches together with the requested page.
+    pub fn search(
+        &self,
+        search: &SearchRequest,
+        options: &SearchOptions,
+        offset: usize,
+        size: usize,
+        now: i64,
+    ) -> (usize, Vec<&Film>) {
+        let parsed = ParsedQuery::parse(&search.query);
+        let (duration_min, duration_max) = search.duration_bounds(&parsed);
+        let lower = |terms: &[String]| -> Vec<String> {
+            terms.iter().map(|term| term.to_lowercase()).collect()
+        };
+        let channels = lower(&parsed.channels);
+        let topics = lower(&parsed.topics);
+        let titles = lower(&parsed.titles);
+        let descriptions = lower(&parsed.descriptions);
+        let words = lower(&parsed.text);
+
+        // an empty list of alternatives matches everything
+        let any = |terms: &[String], value: &str| {
+            terms.is_empty() || {
+                let value = value.to_lowercase();
+                terms.iter().any(|term| value.contains(term.as_str()))
+            }
+        };
+
+        let mut matches: Vec<&Film> = self
+            .films
+            .iter()
+            .filter(|film| film.timestamp <= now)
+            .filter(|film| duration_min.is_none_or(|min| film.duration >= min.as_secs()))
+            .filter(|film| duration_max.is_none_or(|max| film.duration <= max.as_secs()))
+            .filter(|film| {
+                // channels picked in the settings are exact names, typed ones may be partial
+                let picked = options
+                    .channels
+                    .iter()
+                    .any(|channel| channel.eq_ignore_ascii_case(&film.channel));
+                (channels.is_empty() && options.channels.is_empty())
+                    || picked
+                    || (!channels.is_empty() && any(&channels, &film.channel))
+            })
+            .filter(|film| any(&topics, &film.topic))
+            .filter(|film| any(&titles, &film.title))
+            .filter(|film| {
+                any(
+                    &descriptions,
+                    film.description.as_deref().unwrap_or_default(),
+                )
+            })
+            .filter(|film| {
+                let text = format!("{} {}", film.topic, film.title).to_lowercase();
+                words.iter().all(|word| text.contains(word.as_str()))
+            })
+            .collect();
+
+        match options.sort_field {
+            SortField::Channel => matches.sort_by_key(|film| film.channel.to_lowercase()),
+            SortField::Duration => matches.sort_by_key(|film| film.duration),
+            _ => matches.sort_by_key(|film| film.timestamp),
+        }
+        if options.sort_order == SortOrder::Descending {
+            matches.reverse();
+        }
+
+        let total = matches.len();
+        let page = matches.into_iter().skip(offset).take(size).collect();
+        (total, page)
+    }
+
+    /// Adds the films of a diff list, replacing entries with the same id
+    pub fn merge(&mut self, diff: FilmIndex) {
+        let mut positions: std::collections::HashMap<String, usize> = self
+            .films
+            .iter()
+            .enumerate()
+            .map(|(position, film)| (film.id.clone(), position))
+            .collect();
+        for film in diff.films {
+            match positions.get(&film.id) {
+                Some(&position) => self.films[position] = film,
+                None => {
+                    positions.insert(film.id.clone(), self.films.len());
+                    self.films.push(film);
+                }
+            }
+        }
+        self.created = diff.created;
+    }
+}
+
+// reads the film list format, which is a json object whose keys repeat:
+// `"Filmliste"` twice for the header and the column names, then `"X"` once
+// per film
+impl<'de> Deserialize<'de> for FilmlisteFile {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FilmlisteVisitor;
+
+        impl<'de> Visitor<'de> for FilmlisteVisitor {
+            type Value = FilmlisteFile;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("a MediathekView film list")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<FilmlisteFile, A::Error> {
+                let mut index = FilmIndex::default();
+                let mut previous = Film::default();
+                while let Some(key) = map.next_key::<String>()? {
+                    let row: Vec<String> = map.next_value()?;
+                    if key == "Filmliste" {
+                        // the first header holds the creation dates, the second the column names
+                        if index.created.is_empty() {
+                            index.created = row.get(1).or(row.first()).cloned().unwrap_or_default();
+                        }
+                        continue;
+                    }
+                    let film = parse_row(&row, &previous);
+                    index.films.push(film.clone());
+                    previous = film;
+                }
+                Ok(FilmlisteFile(index))
+            }
+        }
+
+        deserializer.deserialize_map(FilmlisteVisitor)
+    }
+}
+
+// wrapper so the file format and the stored index can have their own serde
+struct FilmlisteFile(FilmIndex);
+
+// column positions in a film list row
+const CHANNEL: usize = 0;
+const TOPIC: usize = 1;
+const TITLE: usize = 2;
+const DURATION: usize = 5;
+const SIZE_MB: usize = 6;
+const DESCRIPTION: usize = 7;
+const URL: usize = 8;
+const WEBSITE: usize = 9;
+const URL_SUBTITLE: usize = 10;
+const URL_SMALL: usize = 12;
+const URL_HD: usize = 14;
+const TIMESTAMP: usize = 16;
+
+// converts a row, empty channel and topic are taken from the previous row
+fn parse_row(row: &[String], previous: &Film) -> Film {
+    let column = |index: usize| row.get(index).map(String::as_str).unwrap_or_default();
+    let optional = |value: &str| Some(value.to_string()).filter(|value| !value.is_empty());
+    let inherit = |value: &str, previous: &str| match value {
+        "" => previous.to_string(),
+        value => value.to_string(),
+    };
+
+    let video = column(URL).to_string();
+    let mut film = Film {
+        id: String::new(),
+        channel: inherit(column(CHANNEL), &previous.channel),
+        topic: inherit(column(TOPIC), &previous.topic),
+        title: column(TITLE).to_string(),
+        description: optional(column(DESCRIPTION)),
+        timestamp: column(TIMESTAMP).parse().unwrap_or_default(),
+        duration: parse_duration(column(DURATION)),
+        size: column(SIZE_MB)
+            .parse::<u64>()
+            .ok()
+            .map(|megabytes| megabytes * 1_000_000),
+        website: column(WEBSITE).to_string(),
+        subtitle: optional(column(URL_SUBTITLE)),
+        video_low: expand_url(&video, column(URL_SMALL)),
+        video_hd: expand_url(&video, column(URL_HD)),
+        video,
+    };
+    film.id = film_id(&film);
+    film
+}
+
+// `hh:mm:ss` to seconds
+fn parse_duration(value: &str) -> u64 {
+    value
+        .split(':')
+        .try_fold(0, |total, part| {
+            Some(total * 60 + part.parse::<u64>().ok()?)
+        })
+        .unwrap_or_default()
+}
+
+// the small and hd urls are stored as `<length>|<suffix>`, replacing
+// everything after the first `length` bytes of the normal url
+fn expand_url(base: &str, value: &str) -> Option<String> {
+    if value.is_empty() {
+        return None;
+    }
+    match value.split_once('|') {
+        Some((length, suffix)) => {
+            let prefix = base.get(..length.parse::<usize>().ok()?)?;
+            Some(format!("{prefix}{suffix}"))
+        }
+        None => Some(value.to_string()),
+    }
+}
+
+// stable id from channel, topic, title and url (FNV-1a), so that diff lists
+// can replace entries and selections survive a new import
+fn film_id(film: &Film) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for part in [&film.channel, &film.topic, &film.title, &film.video] {
+        for byte in part.bytes().chain([0]) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    format!("{hash:016x}")
+}
+
+/// Parses a film list, xz compressed or plain json
+pub fn parse(reader: impl Read) -> Result<FilmIndex, FilmlisteError> {
+    let mut reader = BufReader::new(reader);
+    let mut magic = [0; XZ_MAGIC.len()];
+    let header = {
+        use std::io::BufRead;
+        let buffer = reader
+            .fill_buf()
+            .map_err(|error| FilmlisteError::Io(PathBuf::new(), error))?;
+        let length = buffer.len().min(magic.len());
+        magic[..length].copy_from_slice(&buffer[..length]);
+        length
+    };
+    let FilmlisteFile(index) = if magic[..header] == XZ_MAGIC {
+        serde_json::from_reader(BufReader::new(xz2::read::XzDecoder::new(reader)))?
+    } else {
+        serde_json::from_reader(reader)?
+    };
+    Ok(index)
+}
+
+/// Imports a film list file into the index in the data directory.
+///
+/// This reads and writes large files, so it should run on a blocking thread.
+/// Returns the number of films in the index afterwards.
+pub fn import(path: &Path, mode: ImportMode) -> Result<usize, FilmlisteError> {
+    let file =
+        std::fs::File::open(path).map_err(|error| FilmlisteError::Io(path.to_path_buf(), error))?;
+    let imported = parse(file).map_err(|error| match error {
+        FilmlisteError::Io(_, error) => FilmlisteError::Io(path.to_path_buf(), error),
+        error => error,
+    })?;
+
+    let index = match mode {
+        ImportMode::Full => imported,
+        ImportMode::Diff => {
+            let mut index = (*load_index()?).clone();
+            index.merge(imported);
+            index
+        }
+    };
+    storage::save_json_compact(INDEX_FILE, &index)?;
+    let count = index.films.len();
+    *INDEX.lock().unwrap_or_else(|error| error.into_inner()) = Some(Arc::new(index));
+    Ok(count)
+}
+
+/// Gets the imported index, reading it from disk on first use
+pub fn load_index() -> Result<Arc<FilmIndex>, FilmlisteError> {
+    let mut cached = INDEX.lock().unwrap_or_else(|error| error.into_inner());
+    if let Some(index) = cached.as_ref() {
+        return Ok(index.clone());
+    }
+    let index: FilmIndex = storage::load_json(INDEX_FILE)?;
+    if index.films.is_empty() {
+        return Err(FilmlisteError::NoIndex);
+    }
+    let index = Arc::new(index);
+    *cached = Some(index.clone());
+    Ok(index)
+}
+
+/// Searches the imported film list instead of the live API
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FilmlisteBackend;
+
+impl SearchBackend for FilmlisteBackend {
+    async fn search(
+        &self,
+        search: &SearchRequest,
+        options: &SearchOptions,
+        offset: usize,
+        size: usize,
+    ) -> Result<SearchResults, BackendError> {
+        let index = tokio::task::spawn_blocking(load_index)
+            .await
+            .map_err(|_| FilmlisteError::Interrupted)??;
+        let now = chrono::Utc::now().timestamp();
+        let (total, films) = index.search(search, options, offset, size, now);
+        let items: Vec<SearchItem> = films
+            .into_iter()
+            .map(|film| search_backend::search_item(film.to_item(), &options.quality_order))
+            .collect();
+        Ok(SearchResults { total, items })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::Settings;
+
+    const FILMLISTE: &str = r#"{
+        "Filmliste": ["01.02.2024, 10:00", "01.02.2024, 09:00", "3", "MSearch [Vers.: 3.1.219]", "abc"],
+        "Filmliste": ["Sender", "Thema", "Titel", "Datum", "Zeit", "Dauer", "Größe [MB]", "Beschreibung", "Url", "Website", "Url Untertitel", "Url RTMP", "Url Klein", "Url RTMP Klein", "Url HD", "Url RTMP HD", "DatumL", "Url History", "Geo", "neu"],
+        "X": ["ARD", "Tatort", "Der Fall Holdt", "01.01.2024", "20:15:00", "01:29:00", "850", "Kommissarin Lindholm ermittelt", "https://example.org/tatort/sd.mp4", "https://ard.de/tatort", "https://example.org/tatort.xml", "", "27|small.mp4", "", "27|hd.mp4", "", "1704136500", "", "DE", "false"],
+        "X": ["", "", "Im Schmerz geboren", "02.01.2024", "20:15:00", "01:30:00", "", "", "https://example.org/tatort/2.mp4", "", "", "", "", "", "", "", "1704222900", "", "", "false"],
+        "X": ["ZDF", "Terra X", "Eiszeit", "03.01.2024", "19:30:00", "00:43:00", "400", "", "https://example.org/terra.mp4", "", "", "", "", "", "", "", "1704306600", "", "", "true"]
+    }"#;
+
+    fn index() -> FilmIndex {
+        parse(FILMLISTE.as_bytes()).unwrap()
+    }
+
+    fn titles(films: &[&Film]) -> Vec<String> {
+        films.iter().map(|film| film.title.clone()).collect()
+    }
+
+    fn search(index: &FilmIndex, query: &str, options: &SearchOptions) -> (usize, Vec<String>) {
+        let search = SearchRequest {
+            query: String::from(query),
+            ..Default::default()
+        };
+        let (total, films) = index.search(&search, options, 0, 10, i64::MAX);
+        (total, titles(&films))
+    }
+
+    fn options() -> SearchOptions {
+        SearchOptions::from_settings(&Settings::default())
+    }
+
+    #[test]
+    fn test_parse_header_and_rows() {
+        let index = index();
+        assert_eq!(index.created, "01.02.2024, 09:00");
+        assert_eq!(index.films.len(), 3);
+        let film = &index.films[0];
+        assert_eq!(film.duration, 89 * 60);
+        assert_eq!(film.size, Some(850_000_000));
+        assert_eq!(film.timestamp, 1704136500);
+        assert_eq!(
+            film.subtitle.as_deref(),
+            Some("https://example.org/tatort.xml")
+        );
+        assert_eq!(film.id.len(), 16);
+    }
+
+    #[test]
+    fn test_parse_inherits_channel_and_topic() {
+        let film = &index().films[1];
+        assert_eq!(film.channel, "ARD");
+        assert_eq!(film.topic, "Tatort");
+        assert_eq!(film.size, None);
+        assert_eq!(film.video_hd, None);
+    }
+
+    #[test]
+    fn test_expand_url() {
+        let film = &index().films[0];
+        assert_eq!(
+            film.video_low.as_deref(),
+            Some("https://example.org/tatort/small.mp4")
+        );
+        assert_eq!(
+            film.video_hd.as_deref(),
+            Some("https://example.org/tatort/hd.mp4")
+        );
+        assert_eq!(expand_url("short", "99|x.mp4"), None);
+    }
+
+    #[test]
+    fn test_parse_xz() {
+        use std::io::Write;
+        let mut encoder = xz2::write::XzEncoder::new(Vec::new(), 6);
+        encoder.write_all(FILMLISTE.as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+        assert_eq!(parse(compressed.as_slice()).unwrap(), index());
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(matches!(
+            parse("[1, 2]".as_bytes()),
+            Err(FilmlisteError::Json(_))
+        ));
+    }
+
+    #[test]
+    fn test_search_text_and_selectors() {
+        let index = index();
+        assert_eq!(search(&index, "tatort", &options()).0, 2);
+        assert_eq!(
+            search(&index, "tatort schmerz", &options()).1,
+            vec!["Im Schmerz geboren"]
+        );
+        assert_eq!(search(&index, "!zdf", &options()).1, vec!["Eiszeit"]);
+        assert_eq!(search(&index, "!zdf !ard", &options()).0, 3);
+        assert_eq!(
+            search(&index, "*lindholm", &options()).1,
+            vec!["Der Fall Holdt"]
+        );
+        assert_eq!(search(&index, ">60", &options()).0, 2);
+    }
+
+    #[test]
+    fn test_search_channel_filter() {
+        let mut options = options();
+        options.channels = vec![String::from("ZDF")];
+        assert_eq!(search(&index(), "", &options).1, vec!["Eiszeit"]);
+    }
+
+    #[test]
+    fn test_search_channel_filter_is_exact() {
+        let mut index = index();
+        let mut srf = index.films[2].clone();
+        srf.channel = String::from("SRF");
+        srf.title = String::from("Schweiz aktuell");
+        index.films.push(srf);
+
+        let mut picked = options();
+        picked.channels = vec![String::from("SR")];
+        assert_eq!(search(&index, "", &picked).0, 0);
+        picked.channels = vec![String::from("srf")];
+        assert_eq!(search(&index, "", &picked).1, vec!["Schweiz aktuell"]);
+        // a typed selector still matches parts of the name
+        assert_eq!(search(&index, "!sr", &options()).1, vec!["Schweiz aktuell"]);
+    }
+
+    #[test]
+    fn test_search_sort_and_page() {
+        let index = index();
+        assert_eq!(
+            search(&index, "", &options()).1,
+            vec!["Eiszeit", "Im Schmerz geboren", "Der Fall Holdt"]
+        );
+        let mut options = options();
+        options.sort_field = SortField::Duration;
+        options.sort_order = SortOrder::Ascending;
+        let (total, films) = index.search(&SearchRequest::default(), &options, 1, 1, i64::MAX);
+        assert_eq!(total, 3);
+        assert_eq!(titles(&films), vec!["Der Fall Holdt"]);
+    }
+
+    #[test]
+    fn test_search_skips_future() {
+        let (total, _) = index().search(&SearchRequest::default(), &options(), 0, 10, 1704200000);
+        assert_eq!(total, 1);
+    }
+
+    #[test]
+    fn test_merge_diff() {
+        let mut index = index();
+        let mut diff = FilmIndex {
+            created: String::from("02.02.2024, 09:00"),
+            films: vec![index.films[2].clone()],
+        };
+        diff.films[0].description = Some(String::from("neu"));
+        diff.films.push(Film {
+            id: String::from("new"),
+            title: String::from("Neu"),
+            ..Default::default()
+        });
+        index.merge(diff);
+        assert_eq!(index.created, "02.02.2024, 09:00");
+        assert_eq!(index.films.len(), 4);
+        assert_eq!(index.films[2].description.as_deref(), Some("neu"));
+    }
+}
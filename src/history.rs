@@ -0,0 +1,274 @@
+use crate::{
+    search_logic::{SearchOptions, SearchRequest},
+    storage::{self, StorageError},
+};
+use mediathekviewweb::models::{SortField, SortOrder};
+use serde::{Deserialize, Serialize};
+
+const HISTORY_FILE: &str = "history.json";
+
+/// A search that has been run before, together with the page last viewed
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub search: SearchRequest,
+    /// channels the search was restricted to, empty for all channels
+    #[serde(default)]
+    pub channels: Vec<String>,
+    #[serde(default = "default_sort_field")]
+    pub sort_field: SortField,
+    #[serde(default = "default_sort_order")]
+    pub sort_order: SortOrder,
+    pub offset: usize,
+    pub page: usize,
+    pub pinned: bool,
+    /// unix timestamp of the last time the search was run
+    pub last_used: i64,
+}
+
+impl HistoryEntry {
+    /// Gets a short description of the search including its filters
+    pub fn label(&self) -> String {
+        let mut label = if self.search.query.trim().is_empty() {
+            String::from("(all)")
+        } else {
+            self.search.query.trim().to_string()
+        };
+        match (self.search.duration_min, self.search.duration_max) {
+            (Some(min), Some(max)) => label.push_str(&format!(" · {min}–{max} min")),
+            (Some(min), None) => label.push_str(&format!(" · ≥{min} min")),
+            (None, Some(max)) => label.push_str(&format!(" · ≤{max} min")),
+            (None, None) => {}
+        }
+        if !self.channels.is_empty() {
+            label.push_str(&format!(" · {}", self.channels.join(", ")));
+        }
+        if self.page > 1 {
+            label.push_str(&format!(" · page {}", self.page));
+        }
+        label
+    }
+}
+
+// entries from before filters were stored used the default sort
+fn default_sort_field() -> SortField {
+    SortField::Timestamp
+}
+
+fn default_sort_order() -> SortOrder {
+    SortOrder::Descending
+}
+
+/// The last searches, most recent first, stored in the data directory
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SearchHistory {
+    pub entries: Vec<HistoryEntry>,
+}
+
+impl SearchHistory {
+    pub fn load() -> Result<SearchHistory, StorageError> {
+        storage::load_json(HISTORY_FILE)
+    }
+
+    pub fn save(&self) -> Result<(), StorageError> {
+        storage::save_json(HISTORY_FILE, self)
+    }
+
+    /// Remembers a search with its channels and sort order, moving it to the
+    /// top if it was run before with the same channels.
+    ///
+    /// Unpinned entries beyond `limit` are dropped, pinned ones are kept.
+    pub fn record(
+        &mut self,
+        search: &SearchRequest,
+        options: &SearchOptions,
+        offset: usize,
+        page: usize,
+        now: i64,
+        limit: usize,
+    ) {
+        let pinned = match self
+            .entries
+            .iter()
+            .position(|entry| &entry.search == search && entry.channels == options.channels)
+        {
+            Some(index) => self.entries.remove(index).pinned,
+            None => false,
+        };
+        self.entries.insert(
+            0,
+            HistoryEntry {
+                search: search.clone(),
+                channels: options.channels.clone(),
+                sort_field: options.sort_field,
+                sort_order: options.sort_order,
+                offset,
+                page,
+                pinned,
+                last_used: now,
+            },
+        );
+
+        let mut unpinned = 0;
+        self.entries.retain(|entry| {
+            if entry.pinned {
+                return true;
+            }
+            unpinned += 1;
+            unpinned <= limit
+        });
+    }
+
+    /// Gets the entries to display, pinned searches first
+    pub fn sorted(&self) -> Vec<(usize, &HistoryEntry)> {
+        let mut entries: Vec<_> = self.entries.iter().enumerate().collect();
+        entries.sort_by_key(|(_, entry)| !entry.pinned);
+        entries
+    }
+
+    pub fn toggle_pin(&mut self, index: usize) {
+        if let Some(entry) = self.entries.get_mut(index) {
+            entry.pinned = !entry.pinned;
+        }
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        if index < self.entries.len() {
+            self.entries.remove(index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::Settings;
+
+    fn search(query: &str) -> SearchRequest {
+        SearchRequest {
+            query: String::from(query),
+            ..Default::default()
+        }
+    }
+
+    fn options() -> SearchOptions {
+        SearchOptions::from_settings(&Settings::default())
+    }
+
+    fn queries(history: &SearchHistory) -> Vec<&str> {
+        history
+            .entries
+            .iter()
+            .map(|entry| entry.search.query.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_record_most_recent_first() {
+        let mut history = SearchHistory::default();
+        history.record(&search("tatort"), &options(), 0, 1, 1, 10);
+        history.record(&search("polizeiruf"), &options(), 0, 1, 2, 10);
+        assert_eq!(queries(&history), vec!["polizeiruf", "tatort"]);
+    }
+
+    #[test]
+    fn test_record_repeated_search_moves_to_top() {
+        let mut history = SearchHistory::default();
+        history.record(&search("tatort"), &options(), 0, 1, 1, 10);
+        history.record(&search("polizeiruf"), &options(), 0, 1, 2, 10);
+        history.record(&search("tatort"), &options(), 30, 3, 3, 10);
+        assert_eq!(queries(&history), vec!["tatort", "polizeiruf"]);
+        assert_eq!(history.entries[0].offset, 30);
+        assert_eq!(history.entries[0].page, 3);
+    }
+
+    #[test]
+    fn test_record_keeps_pin() {
+        let mut history = SearchHistory::default();
+        history.record(&search("tatort"), &options(), 0, 1, 1, 10);
+        history.toggle_pin(0);
+        history.record(&search("tatort"), &options(), 15, 2, 2, 10);
+        assert!(history.entries[0].pinned);
+    }
+
+    #[test]
+    fn test_record_limit_spares_pinned() {
+        let mut history = SearchHistory::default();
+        history.record(&search("pinned"), &options(), 0, 1, 1, 2);
+        history.toggle_pin(0);
+        history.record(&search("a"), &options(), 0, 1, 2, 2);
+        history.record(&search("b"), &options(), 0, 1, 3, 2);
+        history.record(&search("c"), &options(), 0, 1, 4, 2);
+        assert_eq!(queries(&history), vec!["c", "b", "pinned"]);
+    }
+
+    #[test]
+    fn test_sorted_pinned_first() {
+        let mut history = SearchHistory::default();
+        history.record(&search("a"), &options(), 0, 1, 1, 10);
+        history.record(&search("b"), &options(), 0, 1, 2, 10);
+        history.toggle_pin(1);
+        let sorted: Vec<_> = history
+            .sorted()
+            .into_iter()
+            .map(|(index, entry)| (index, entry.search.query.as_str()))
+            .collect();
+        assert_eq!(sorted, vec![(1, "a"), (0, "b")]);
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut history = SearchHistory::default();
+        history.record(&search("a"), &options(), 0, 1, 1, 10);
+        history.remove(0);
+        history.remove(5);
+        assert!(history.entries.is_empty());
+    }
+
+    #[test]
+    fn test_label() {
+        let entry = HistoryEntry {
+            search: SearchRequest {
+                query: String::from("tatort "),
+                duration_min: Some(80),
+                duration_max: None,
+            },
+            channels: vec![String::from("ARD"), String::from("ZDF")],
+            sort_field: SortField::Timestamp,
+            sort_order: SortOrder::Descending,
+            offset: 30,
+            page: 3,
+            pinned: false,
+            last_used: 0,
+        };
+        assert_eq!(entry.label(), "tatort · ≥80 min · ARD, ZDF · page 3");
+    }
+
+    #[test]
+    fn test_record_keeps_channels_and_sort() {
+        let mut history = SearchHistory::default();
+        let mut filtered = options();
+        filtered.channels = vec![String::from("ARD")];
+        filtered.sort_field = SortField::Duration;
+        history.record(&search("tatort"), &filtered, 0, 1, 1, 10);
+        history.record(&search("tatort"), &options(), 0, 1, 2, 10);
+        assert_eq!(queries(&history), vec!["tatort", "tatort"]);
+        let entry = &history.entries[1];
+        assert_eq!(entry.channels, vec!["ARD"]);
+        assert_eq!(entry.sort_field, SortField::Duration);
+
+        // sorting the same search differently updates its entry
+        filtered.sort_order = SortOrder::Ascending;
+        history.record(&search("tatort"), &filtered, 0, 1, 3, 10);
+        assert_eq!(history.entries.len(), 2);
+        assert_eq!(history.entries[0].sort_order, SortOrder::Ascending);
+    }
+
+    #[test]
+    fn test_old_entries_use_default_filters() {
+        let json = r#"{"search":{"query":"tatort","duration_min":null,"duration_max":null},"offset":0,"page":1,"pinned":false,"last_used":0}"#;
+        let entry: HistoryEntry = serde_json::from_str(json).unwrap();
+        assert!(entry.channels.is_empty());
+        assert_eq!(entry.sort_field, SortField::Timestamp);
+        assert_eq!(entry.sort_order, SortOrder::Descending);
+    }
+}
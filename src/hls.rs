@@ -0,0 +1,493 @@
+use crate::{
+    bandwidth::Limiter,
+    download::{DownloadError, Progress},
+    resume,
+    settings::Quality,
+};
+use reqwest::Url;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio::task::JoinSet;
+
+// number of segments fetched at the same time
+const CONCURRENT_SEGMENTS: usize = 4;
+
+// segments fetched ahead of the one written next, limits the memory used
+// while waiting for a slow segment
+const SEGMENT_WINDOW: usize = 2 * CONCURRENT_SEGMENTS;
+
+// attempts per segment before the download fails
+const SEGMENT_ATTEMPTS: u32 = 3;
+
+// pause before the first retry, doubled for every further one
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+
+// minimum time between two progress reports
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+// height SD variants are picked by, the PAL resolution most broadcasters use
+const SD_HEIGHT: u32 = 576;
+
+#[derive(Debug, thiserror::Error)]
+pub enum HlsError {
+    #[error("invalid playlist: {0}")]
+    Invalid(String),
+    #[error("encrypted streams are not supported")]
+    Encrypted,
+    #[error("live streams can't be downloaded")]
+    Live,
+    #[error("the playlist has no segments")]
+    NoSegments,
+    #[error("segment download was interrupted")]
+    Interrupted,
+}
+
+/// A stream of the master playlist in one quality
+#[derive(Clone, Debug, PartialEq)]
+pub struct Variant {
+    pub uri: String,
+    /// bits per second
+    pub bandwidth: u64,
+    pub height: Option<u32>,
+}
+
+/// The segments of a stream in playback order
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MediaPlaylist {
+    /// initialization section of fragmented mp4 streams
+    pub init: Option<String>,
+    pub segments: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Playlist {
+    Master(Vec<Variant>),
+    Media(MediaPlaylist),
+}
+
+/// Checks whether a url points to an HLS playlist instead of a video file
+pub fn is_playlist(url: &str) -> bool {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    path.to_lowercase().ends_with(".m3u8")
+}
+
+/// Parses a master or media playlist
+pub fn parse(content: &str) -> Result<Playlist, HlsError> {
+    let mut lines = content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty());
+    if lines.next() != Some("#EXTM3U") {
+        return Err(HlsError::Invalid(String::from("missing #EXTM3U header")));
+    }
+
+    let mut variants = Vec::new();
+    let mut media = MediaPlaylist::default();
+    let mut pending_variant: Option<Variant> = None;
+    let mut ended = false;
+    for line in lines {
+        if let Some(list) = line.strip_prefix("#EXT-X-STREAM-INF:") {
+            let attributes = attributes(list);
+            let bandwidth = attribute(&attributes, "BANDWIDTH")
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(0);
+            let height = attribute(&attributes, "RESOLUTION")
+                .and_then(|value| value.split_once('x'))
+                .and_then(|(_, height)| height.parse().ok());
+            pending_variant = Some(Variant {
+                uri: String::new(),
+                bandwidth,
+                height,
+            });
+        } else if let Some(list) = line.strip_prefix("#EXT-X-KEY:") {
+            if attribute(&attributes(list), "METHOD") != Some("NONE") {
+                return Err(HlsError::Encrypted);
+            }
+        } else if let Some(list) = line.strip_prefix("#EXT-X-MAP:") {
+            media.init = attribute(&attributes(list), "URI").map(str::to_string);
+        } else if line == "#EXT-X-ENDLIST" {
+            ended = true;
+        } else if line.starts_with('#') {
+            // comments and tags that don't matter for downloading
+        } else if let Some(mut variant) = pending_variant.take() {
+            variant.uri = line.to_string();
+            variants.push(variant);
+        } else {
+            media.segments.push(line.to_string());
+        }
+    }
+
+    if !variants.is_empty() {
+        return Ok(Playlist::Master(variants));
+    }
+    if media.segments.is_empty() {
+        return Err(HlsError::NoSegments);
+    }
+    if !ended {
+        return Err(HlsError::Live);
+    }
+    Ok(Playlist::Media(media))
+}
+
+// splits an attribute list like `BANDWIDTH=800000,CODECS="avc1,mp4a"`,
+// removing the quotes around values
+fn attributes(list: &str) -> Vec<(&str, &str)> {
+    let mut attributes = Vec::new();
+    let mut rest = list;
+    while let Some((name, value)) = rest.split_once('=') {
+        let (value, remaining) = match value.strip_prefix('"') {
+            Some(quoted) => match quoted.split_once('"') {
+                Some((value, remaining)) => (value, remaining),
+                None => (quoted, ""),
+            },
+            None => value.split_once(',').unwrap_or((value, "")),
+        };
+        attributes.push((name.trim(), value));
+        rest = remaining.trim_start_matches(',');
+    }
+    attributes
+}
+
+fn attribute<'a>(attributes: &[(&str, &'a str)], name: &str) -> Option<&'a str> {
+    attributes
+        .iter()
+        .find(|(candidate, _)| *candidate == name)
+        .map(|(_, value)| *value)
+}
+
+/// Picks the variant matching a quality: the best one for HD, the worst one
+/// for LQ and the one closest to PAL resolution for SD. Without a quality the
+/// best one is used.
+pub fn select_variant(variants: &[Variant], quality: Option<Quality>) -> Option<&Variant> {
+    let mut sorted: Vec<&Variant> = variants.iter().collect();
+    sorted.sort_by_key(|variant| (variant.height, variant.bandwidth));
+    match quality {
+        None | Some(Quality::HD) => sorted.last().copied(),
+        Some(Quality::LQ) => sorted.first().copied(),
+        Some(Quality::SD) => sorted
+            .iter()
+            .rev()
+            .min_by_key(|variant| variant.height.unwrap_or(0).abs_diff(SD_HEIGHT))
+            .copied(),
+    }
+}
+
+fn resolve(base: &Url, uri: &str) -> Result<Url, HlsError> {
+    base.join(uri)
+        .map_err(|error| HlsError::Invalid(format!("{uri}: {error}")))
+}
+
+async fn fetch_text(client: &reqwest::Client, url: &Url) -> Result<String, reqwest::Error> {
+    client
+        .get(url.clone())
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await
+}
+
+// fetches the media playlist, following a master playlist to the variant
+// of the requested quality
+async fn media_playlist(
+    client: &reqwest::Client,
+    url: &str,
+    quality: Option<Quality>,
+) -> Result<(Url, MediaPlaylist), DownloadError> {
+    let url = Url::parse(url).map_err(|error| HlsError::Invalid(error.to_string()))?;
+    match parse(&fetch_text(client, &url).await?)? {
+        Playlist::Media(media) => Ok((url, media)),
+        Playlist::Master(variants) => {
+            let variant = select_variant(&variants, quality).ok_or(HlsError::NoSegments)?;
+            let url = resolve(&url, &variant.uri)?;
+            match parse(&fetch_text(client, &url).await?)? {
+                Playlist::Media(media) => Ok((url, media)),
+                Playlist::Master(_) => {
+                    Err(HlsError::Invalid(String::from("nested master playlists")).into())
+                }
+            }
+        }
+    }
+}
+
+// fetches a single segment, trying again after a growing pause if it fails
+// or arrives shorter or longer than announced
+async fn fetch_segment(client: reqwest::Client, url: Url) -> Result<Vec<u8>, DownloadError> {
+    let mut attempt = 1;
+    loop {
+        let result = async {
+            let response = client.get(url.clone()).send().await?.error_for_status()?;
+            let expected = response.content_length();
+            let body = response.bytes().await?.to_vec();
+            match expected {
+                Some(expected) if expected != body.len() as u64 => Err(DownloadError::Incomplete {
+                    expected,
+                    received: body.len() as u64,
+                }),
+                _ => Ok(body),
+            }
+        }
+        .await;
+        match result {
+            Err(_) if attempt < SEGMENT_ATTEMPTS => {
+                tokio::time::sleep(RETRY_DELAY * 2u32.pow(attempt - 1)).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Downloads an HLS stream into a single file at `destination`.
+///
+/// Segments are fetched concurrently and appended in playback order to a
+/// `.part` file, which is renamed once the stream is complete. Unlike plain
+/// files, an aborted stream starts over on the next attempt.
+pub async fn download(
+    client: &reqwest::Client,
+    url: &str,
+    destination: &Path,
+    quality: Option<Quality>,
+    mut on_progress: impl FnMut(Progress),
+) -> Result<u64, DownloadError> {
+    let (base, media) = media_playlist(client, url, quality).await?;
+    let urls = media
+        .init
+        .iter()
+        .chain(&media.segments)
+        .map(|uri| resolve(&base, uri))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if let Some(parent) = destination.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let part = resume::part_path(destination);
+    let mut file = tokio::fs::File::create(&part).await?;
+
+    let started = Instant::now();
+    let mut last_report = started;
+    let mut downloaded = 0;
+    let mut limiter = Limiter::default();
+    let mut running = JoinSet::new();
+    // segments that arrived before the ones preceding them
+    let mut waiting: BTreeMap<usize, Vec<u8>> = BTreeMap::new();
+    let (mut next_to_fetch, mut next_to_write) = (0, 0);
+    while next_to_write < urls.len() {
+        while running.len() < CONCURRENT_SEGMENTS
+            && next_to_fetch < urls.len()
+            && next_to_fetch < next_to_write + SEGMENT_WINDOW
+        {
+            let segment = fetch_segment(client.clone(), urls[next_to_fetch].clone());
+            let index = next_to_fetch;
+            running.spawn(async move { (index, segment.await) });
+            next_to_fetch += 1;
+        }
+
+        let (index, segment) = running
+            .join_next()
+            .await
+            .ok_or(HlsError::Interrupted)?
+            .map_err(|_| HlsError::Interrupted)?;
+        waiting.insert(index, segment?);
+        while let Some(segment) = waiting.remove(&next_to_write) {
+            limiter.consume(segment.len() as u64).await;
+            file.write_all(&segment).await?;
+            downloaded += segment.len() as u64;
+            next_to_write += 1;
+        }
+
+        if last_report.elapsed() >= PROGRESS_INTERVAL && next_to_write > 0 {
+            last_report = Instant::now();
+            on_progress(Progress {
+                downloaded,
+                // assumes the remaining segments are as large as the written ones
+                total: Some(downloaded * urls.len() as u64 / next_to_write as u64),
+                speed: downloaded as f64 / started.elapsed().as_secs_f64(),
+            });
+        }
+    }
+    file.flush().await?;
+    drop(file);
+    tokio::fs::rename(&part, destination).await?;
+
+    on_progress(Progress {
+        downloaded,
+        total: Some(downloaded),
+        speed: downloaded as f64 / started.elapsed().as_secs_f64().max(f64::EPSILON),
+    });
+    Ok(downloaded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, block_on, Response};
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    const MASTER: &str = "#EXTM3U
+#EXT-X-STREAM-INF:BANDWIDTH=500000,RESOLUTION=640x360,CODECS=\"avc1.4d401e,mp4a.40.2\"
+low/index.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=3000000,RESOLUTION=1920x1080
+https://cdn.example.org/hd/index.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=1500000,RESOLUTION=1280x720
+mid/index.m3u8
+";
+
+    const MEDIA: &str = "#EXTM3U
+#EXT-X-TARGETDURATION:10
+#EXTINF:10.0,
+segment0.ts
+#EXTINF:10.0,
+segment1.ts
+#EXTINF:4.5,
+/absolute/segment2.ts
+#EXT-X-ENDLIST
+";
+
+    // serves fixed files and counts the requests per path, the paths in
+    // `flaky` answer their first request with an error
+    fn serve(
+        files: Vec<(&'static str, Vec<u8>)>,
+        flaky: &'static [&'static str],
+    ) -> (String, Arc<Mutex<HashMap<String, usize>>>) {
+        let files: HashMap<&str, Vec<u8>> = files.into_iter().collect();
+        testing::serve(move |request| match files.get(request.path.as_str()) {
+            Some(_) if request.count == 1 && flaky.contains(&request.path.as_str()) => {
+                Response::status("500 Internal Server Error")
+            }
+            Some(body) => Response::ok(body.clone()),
+            None => Response::status("404 Not Found"),
+        })
+    }
+
+    #[test]
+    fn test_is_playlist() {
+        assert!(is_playlist("https://example.org/master.M3U8?token=1"));
+        assert!(!is_playlist("https://example.org/video.mp4"));
+        assert!(!is_playlist("https://example.org/m3u8/video.mp4"));
+    }
+
+    #[test]
+    fn test_parse_master() {
+        let Playlist::Master(variants) = parse(MASTER).unwrap() else {
+            panic!("expected a master playlist");
+        };
+        assert_eq!(variants.len(), 3);
+        assert_eq!(
+            variants[0],
+            Variant {
+                uri: String::from("low/index.m3u8"),
+                bandwidth: 500_000,
+                height: Some(360),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_media() {
+        let content = "#EXTM3U\n#EXT-X-MAP:URI=\"init.mp4\"\n#EXTINF:6,\na.m4s\n#EXT-X-ENDLIST\n";
+        assert_eq!(
+            parse(content).unwrap(),
+            Playlist::Media(MediaPlaylist {
+                init: Some(String::from("init.mp4")),
+                segments: vec![String::from("a.m4s")],
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(matches!(parse("segment.ts"), Err(HlsError::Invalid(_))));
+        assert!(matches!(
+            parse("#EXTM3U\n#EXT-X-ENDLIST\n"),
+            Err(HlsError::NoSegments)
+        ));
+        assert!(matches!(
+            parse("#EXTM3U\n#EXTINF:10,\na.ts\n"),
+            Err(HlsError::Live)
+        ));
+        assert!(matches!(
+            parse("#EXTM3U\n#EXT-X-KEY:METHOD=AES-128,URI=\"key\"\n#EXTINF:10,\na.ts\n#EXT-X-ENDLIST\n"),
+            Err(HlsError::Encrypted)
+        ));
+    }
+
+    #[test]
+    fn test_select_variant() {
+        let Playlist::Master(variants) = parse(MASTER).unwrap() else {
+            panic!("expected a master playlist");
+        };
+        let height = |quality| select_variant(&variants, quality).unwrap().height;
+        assert_eq!(height(Some(Quality::HD)), Some(1080));
+        assert_eq!(height(Some(Quality::SD)), Some(720));
+        assert_eq!(height(Some(Quality::LQ)), Some(360));
+        assert_eq!(height(None), Some(1080));
+        assert_eq!(select_variant(&[], Some(Quality::HD)), None);
+    }
+
+    #[test]
+    fn test_download_concatenates_segments_in_order() {
+        let (base, requests) = serve(
+            vec![
+                ("/master.m3u8", MASTER.as_bytes().to_vec()),
+                ("/mid/index.m3u8", MEDIA.as_bytes().to_vec()),
+                ("/mid/segment0.ts", vec![0; 1000]),
+                ("/mid/segment1.ts", vec![1; 10]),
+                ("/absolute/segment2.ts", vec![2; 500]),
+            ],
+            &["/mid/segment1.ts"],
+        );
+        let directory = std::env::temp_dir().join(format!("medow-hls-{}", std::process::id()));
+        let destination = directory.join("stream.ts");
+
+        let client = reqwest::Client::new();
+        let mut reports = Vec::new();
+        let bytes = block_on(download(
+            &client,
+            &format!("{base}/master.m3u8"),
+            &destination,
+            Some(Quality::SD),
+            |progress| reports.push(progress),
+        ))
+        .unwrap();
+
+        let content = std::fs::read(&destination).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(bytes, 1510);
+        let mut expected = vec![0; 1000];
+        expected.extend([1; 10]);
+        expected.extend([2; 500]);
+        assert_eq!(content, expected);
+        assert_eq!(requests.lock().unwrap()["/mid/segment1.ts"], 2);
+        assert_eq!(reports.last().unwrap().total, Some(1510));
+    }
+
+    #[test]
+    fn test_download_fails_after_retries() {
+        let (base, requests) = serve(
+            vec![(
+                "/index.m3u8",
+                b"#EXTM3U\n#EXTINF:10,\nmissing.ts\n#EXT-X-ENDLIST\n".to_vec(),
+            )],
+            &[],
+        );
+        let directory =
+            std::env::temp_dir().join(format!("medow-hls-missing-{}", std::process::id()));
+        let result = block_on(download(
+            &reqwest::Client::new(),
+            &format!("{base}/index.m3u8"),
+            &directory.join("stream.ts"),
+            None,
+            |_| {},
+        ));
+        let _ = std::fs::remove_dir_all(&directory);
+        assert!(matches!(result, Err(DownloadError::Http(_))));
+        assert_eq!(
+            requests.lock().unwrap()["/missing.ts"],
+            SEGMENT_ATTEMPTS as usize
+        );
+    }
+}
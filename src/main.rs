@@ -1,9 +1,37 @@
 use dioxus::desktop::tao;
 use dioxus::prelude::*;
+use std::collections::HashMap;
 
+mod bandwidth;
+mod cli;
+mod disk;
+mod download;
+mod download_log;
+mod download_view;
+mod error;
+mod error_view;
+mod filename;
+mod filesize;
+mod filmliste;
+mod history;
+mod hls;
 mod pagination;
+mod query_parser;
+mod resume;
+mod schedule;
+mod search_backend;
 mod search_logic;
 mod search_view;
+mod selection;
+mod settings;
+mod settings_view;
+mod storage;
+mod subscriptions;
+mod subscriptions_view;
+mod subtitles;
+// fixtures shared by the tests
+#[cfg(test)]
+mod testing;
 mod utils;
 
 const FAVICON: Asset = asset!("/assets/favicon.ico");
@@ -13,28 +41,193 @@ const MAIN_CSS: Asset = asset!("/assets/main.css");
 const MEDOW_USER_AGENT: &str = "Mozilla/5.0 Linux Medow/0.1";
 
 // Enumeration to define the navigatable views
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq)]
 enum View {
     Search,
     Settings,
     Download,
+    Subscriptions,
 }
 
 // struct representing a global application wide state
 struct AppState {
     view: View,
-    error: Option<String>,
+    /// errors waiting to be shown, the oldest first
+    errors: Vec<error::ErrorReport>,
+    /// set while config.toml couldn't be read, so saving doesn't overwrite
+    /// it with the defaults
+    config_locked: bool,
+    /// set while history.json couldn't be read, so a search doesn't replace
+    /// the stored history with just itself
+    history_locked: bool,
+    /// set while subscriptions.json couldn't be read, checks and saving wait
+    /// until it loads so the subscriptions aren't replaced by an empty list
+    subscriptions_locked: bool,
     is_loading: bool,
+    settings: settings::Settings,
+    history: history::SearchHistory,
+    subscriptions: subscriptions::Subscriptions,
+    download_log: download_log::DownloadLog,
 }
 
-// init application wide state
-static APP_STATE: GlobalSignal<AppState> = Signal::global(|| AppState {
-    view: View::Search,
-    error: Option::None,
-    is_loading: false,
+// init application wide state, reading settings and search history from disk
+static APP_STATE: GlobalSignal<AppState> = Signal::global(|| {
+    let mut errors = Vec::new();
+    let mut config_locked = false;
+    let mut history_locked = false;
+    let mut subscriptions_locked = false;
+    let settings = settings::Settings::load().unwrap_or_else(|e| {
+        let (report, locked) = unreadable_settings(e);
+        errors.push(report);
+        config_locked = locked;
+        settings::Settings::default()
+    });
+    let history = history::SearchHistory::load().unwrap_or_else(|e| {
+        let (report, locked) = unreadable_data(e, reload_history);
+        errors.push(report);
+        history_locked = locked;
+        history::SearchHistory::default()
+    });
+    let subscriptions = subscriptions::Subscriptions::load().unwrap_or_else(|e| {
+        let (report, locked) = unreadable_data(e, reload_subscriptions);
+        errors.push(report);
+        subscriptions_locked = locked;
+        subscriptions::Subscriptions::default()
+    });
+    let download_log = download_log::DownloadLog::load().unwrap_or_else(|e| {
+        errors.push(error::ErrorReport::with_retry(e, reload_download_log));
+        download_log::DownloadLog::default()
+    });
+    AppState {
+        view: View::Search,
+        errors,
+        config_locked,
+        history_locked,
+        subscriptions_locked,
+        is_loading: false,
+        settings,
+        history,
+        subscriptions,
+        download_log,
+    }
 });
 
+// moves a config file that can't be parsed aside and returns the error to
+// show; if it couldn't be read at all, saving is blocked until it loads
+fn unreadable_settings(e: settings::SettingsError) -> (error::ErrorReport, bool) {
+    match settings::set_aside(e) {
+        e @ settings::SettingsError::BackedUp(..) => (error::ErrorReport::new(e), false),
+        e => (error::ErrorReport::with_retry(e, reload_settings), true),
+    }
+}
+
+// moves a data file that can't be parsed aside and returns the error to
+// show; if it couldn't be read at all, saving is blocked until `reload` works
+fn unreadable_data(e: storage::StorageError, reload: fn()) -> (error::ErrorReport, bool) {
+    match storage::set_aside(e) {
+        e @ storage::StorageError::BackedUp(..) => (error::ErrorReport::new(e), false),
+        e => (error::ErrorReport::with_retry(e, reload), true),
+    }
+}
+
+// reads the settings again after loading them failed
+fn reload_settings() {
+    match settings::Settings::load() {
+        Ok(settings) => {
+            let mut state = APP_STATE.write();
+            state.settings = settings;
+            state.config_locked = false;
+        }
+        Err(e) => error::report_with_retry(e, reload_settings),
+    }
+}
+
+// reads the search history again after loading it failed
+fn reload_history() {
+    match history::SearchHistory::load() {
+        Ok(history) => {
+            let mut state = APP_STATE.write();
+            state.history = history;
+            state.history_locked = false;
+        }
+        Err(e) => error::report_with_retry(e, reload_history),
+    }
+}
+
+// reads the subscriptions again after loading them failed
+fn reload_subscriptions() {
+    match subscriptions::Subscriptions::load() {
+        Ok(subscriptions) => {
+            let mut state = APP_STATE.write();
+            state.subscriptions = subscriptions;
+            state.subscriptions_locked = false;
+        }
+        Err(e) => error::report_with_retry(e, reload_subscriptions),
+    }
+}
+
+// reads the download history again after loading it failed
+fn reload_download_log() {
+    match download_log::DownloadLog::load() {
+        Ok(download_log) => APP_STATE.write().download_log = download_log,
+        Err(e) => error::report_with_retry(e, reload_download_log),
+    }
+}
+
+/// Writes the current settings to disk and returns whether that worked
+fn save_settings() -> bool {
+    if APP_STATE.read().config_locked {
+        error::report_with_retry(
+            error::AppError::Config(String::from(
+                "settings are not saved while the config file can't be read",
+            )),
+            reload_settings,
+        );
+        return false;
+    }
+    let settings = APP_STATE.read().settings.clone();
+    match settings.save() {
+        Ok(()) => true,
+        Err(e) => {
+            error::report_with_retry(e, || {
+                save_settings();
+            });
+            false
+        }
+    }
+}
+
+/// Writes the current search history to disk, unless the stored one
+/// couldn't be read
+fn save_history() {
+    if APP_STATE.read().history_locked {
+        error::report_with_retry(
+            error::AppError::Filesystem(String::from(
+                "the search history is not saved while the stored one can't be read",
+            )),
+            reload_history,
+        );
+        return;
+    }
+    let history = APP_STATE.read().history.clone();
+    if let Err(e) = history.save() {
+        error::report_with_retry(e, save_history);
+    }
+}
+
+// list of all downloads, including those left over from the last session
+static DOWNLOADS: GlobalSignal<Vec<download::DownloadItem>> = Signal::global(Vec::new);
+
+// sizes of the video files seen in search results, keyed by url
+static FILE_SIZES: GlobalSignal<HashMap<String, filesize::FileSize>> = Signal::global(HashMap::new);
+
 fn main() {
+    // `medow search ...` and `medow download ...` run without a window
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(exit_code) = cli::run(&args) {
+        std::process::exit(exit_code);
+    }
+
     // There are some issues on wayland like the window buttons
     // not reacting - so fallback to x11
     std::env::set_var("GDK_BACKEND", "x11");
@@ -50,11 +243,30 @@ fn main() {
 
 #[component]
 fn App() -> Element {
+    // kept here so search results survive switching between views
+    let pagination = use_signal(pagination::Pagination::new);
+    let selection = use_signal(selection::Selection::default);
+    let view = APP_STATE.read().view;
+    use_hook(|| bandwidth::apply(&APP_STATE.read().settings));
+    // unfinished downloads of the last session continue right away
+    use_hook(download::restore_queue);
+    // new episodes are fetched in the background while the window is open
+    use_hook(subscriptions::start_polling);
+
     rsx! {
         document::Link { rel: "icon", href: FAVICON }
         document::Link { rel: "stylesheet", href: PICO_CSS }
         document::Link { rel: "stylesheet", href: MAIN_CSS }
-        div { class: "layout-container", search_view::search_view {} }
+        error_view::error_banner {}
+        div {
+            class: "layout-container",
+            match view {
+                View::Search => rsx! { search_view::search_view { pagination, selection } },
+                View::Settings => rsx! { settings_view::settings_view {} },
+                View::Download => rsx! { download_view::download_view {} },
+                View::Subscriptions => rsx! { subscriptions_view::subscriptions_view {} },
+            }
+        }
 
     }
 }
@@ -1,17 +1,87 @@
-#[derive(Clone, Debug, PartialEq)]
+use crate::{search_backend::SearchResults, search_logic::SearchRequest, settings::Quality};
+
+/// The video urls of a search result, one per quality
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VideoUrls {
+    pub hd: Option<String>,
+    pub sd: Option<String>,
+    pub low: Option<String>,
+}
+
+impl VideoUrls {
+    /// Gets the url for the given quality if the broadcaster offers it
+    pub fn get(&self, quality: Quality) -> Option<&String> {
+        match quality {
+            Quality::HD => self.hd.as_ref(),
+            Quality::SD => self.sd.as_ref(),
+            Quality::LQ => self.low.as_ref(),
+        }
+    }
+
+    /// Gets the qualities that are available, best first
+    pub fn available(&self) -> Vec<Quality> {
+        Quality::ALL
+            .into_iter()
+            .filter(|quality| self.get(*quality).is_some())
+            .collect()
+    }
+
+    /// Picks the first available quality of the preference order,
+    /// falling back to any other available one
+    pub fn select(&self, order: &[Quality]) -> Option<(Quality, &String)> {
+        order
+            .iter()
+            .chain(Quality::ALL.iter())
+            .find_map(|quality| self.get(*quality).map(|url| (*quality, url)))
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct SearchItem {
-    pub selected: bool,
+    pub channel: String,
     pub title: String,
     pub topic: String,
     pub timestamp: String,
     pub duration: String,
     pub quality: String,
     pub video_url: String,
+    pub video_urls: VideoUrls,
+    /// TTML subtitles offered by the broadcaster
+    pub subtitle_url: Option<String>,
+    pub id: String,
+    pub description: Option<String>,
+    pub website_url: String,
+    /// file size in bytes as reported by the API
+    pub size: Option<u64>,
+    /// unix timestamp of the broadcast
+    pub published: i64,
+}
+
+impl SearchItem {
+    /// Gets an id that identifies the result across pages and searches
+    pub fn key(&self) -> &str {
+        if self.id.is_empty() {
+            &self.video_url
+        } else {
+            &self.id
+        }
+    }
+
+    /// Switches to another quality if the item is available in it
+    pub fn select_quality(&mut self, quality: Quality) {
+        if let Some(url) = self.video_urls.get(quality) {
+            self.video_url = url.clone();
+            self.quality = quality.to_string();
+        }
+    }
 }
 
 pub struct Pagination {
+    /// the search the current results belong to, reused when changing pages
+    pub search: SearchRequest,
     pub total: usize,
     pub offset: usize,
+    pub page_size: usize,
     pub items: Vec<SearchItem>,
 }
 
@@ -19,15 +89,33 @@ impl Pagination {
     /// Creates a new Pagination instance with default values
     pub fn new() -> Self {
         Pagination {
+            search: SearchRequest::default(),
             total: 0,
             offset: 0,
+            page_size: 15,
             items: Vec::new(),
         }
     }
 
-    /// Gets the page size (fixed at 15 as specified)
+    /// Replaces the shown results with a page loaded for `search`
+    pub fn show(
+        &mut self,
+        search: SearchRequest,
+        offset: usize,
+        page_size: usize,
+        results: SearchResults,
+    ) {
+        self.search = search;
+        self.total = results.total;
+        self.offset = offset;
+        // a page size of 0 would divide by zero in the page numbers
+        self.page_size = page_size.max(1);
+        self.items = results.items;
+    }
+
+    /// Gets the page size (15 unless configured otherwise in the settings)
     pub fn page_size(&self) -> usize {
-        15
+        self.page_size
     }
 
     /// Calculates the total number of pages
@@ -35,7 +123,7 @@ impl Pagination {
         if self.total == 0 {
             0
         } else {
-            (self.total + self.page_size() - 1) / self.page_size()
+            self.total.div_ceil(self.page_size())
         }
     }
 
@@ -77,14 +165,34 @@ impl Pagination {
         }
     }
 
+    /// Gets the offset of the first item on the last page
+    pub fn last_offset(&self) -> usize {
+        self.offset_for_page(self.total_pages().max(1))
+    }
+
+    /// Gets the offset of the first item on the given page (1-indexed)
+    pub fn offset_for_page(&self, page: usize) -> usize {
+        page.saturating_sub(1) * self.page_size()
+    }
+
+    /// Gets up to `count` page numbers centered around the current page
+    pub fn page_numbers(&self, count: usize) -> Vec<usize> {
+        let total_pages = self.total_pages();
+        if total_pages == 0 || count == 0 {
+            return Vec::new();
+        }
+        let count = count.min(total_pages);
+        let first = self
+            .current_page()
+            .saturating_sub(count / 2)
+            .clamp(1, total_pages - count + 1);
+        (first..first + count).collect()
+    }
+
     /// Gets the number of items on the current page
     pub fn items_on_page(&self) -> usize {
-        let remaining = self.total - self.offset;
-        if remaining >= self.page_size() {
-            self.page_size()
-        } else {
-            remaining
-        }
+        let (start, end) = self.item_range();
+        end.saturating_sub(start)
     }
 
     /// Checks if the pagination is valid (offset is within bounds)
@@ -116,6 +224,73 @@ impl Pagination {
 mod tests {
     use super::*;
 
+    fn video_urls(hd: bool, sd: bool, low: bool) -> VideoUrls {
+        VideoUrls {
+            hd: hd.then(|| String::from("hd.mp4")),
+            sd: sd.then(|| String::from("sd.mp4")),
+            low: low.then(|| String::from("low.mp4")),
+        }
+    }
+
+    #[test]
+    fn test_video_urls_select_preferred() {
+        let urls = video_urls(true, true, true);
+        assert_eq!(
+            urls.select(&[Quality::HD, Quality::SD, Quality::LQ]),
+            Some((Quality::HD, &String::from("hd.mp4")))
+        );
+        assert_eq!(
+            urls.select(&[Quality::LQ, Quality::SD, Quality::HD]),
+            Some((Quality::LQ, &String::from("low.mp4")))
+        );
+    }
+
+    #[test]
+    fn test_video_urls_select_falls_back() {
+        let urls = video_urls(false, true, true);
+        assert_eq!(
+            urls.select(&[Quality::HD, Quality::LQ]),
+            Some((Quality::LQ, &String::from("low.mp4")))
+        );
+        assert_eq!(
+            urls.select(&[Quality::HD]),
+            Some((Quality::SD, &String::from("sd.mp4")))
+        );
+    }
+
+    #[test]
+    fn test_video_urls_select_none() {
+        assert_eq!(video_urls(false, false, false).select(&Quality::ALL), None);
+    }
+
+    #[test]
+    fn test_video_urls_available() {
+        assert_eq!(
+            video_urls(true, false, true).available(),
+            vec![Quality::HD, Quality::LQ]
+        );
+    }
+
+    #[test]
+    fn test_select_quality() {
+        let mut item = SearchItem {
+            channel: String::from("ARD"),
+            title: String::from("title"),
+            topic: String::from("topic"),
+            timestamp: String::from("01.01.2024"),
+            duration: String::from("0:45"),
+            quality: String::from("SD"),
+            video_url: String::from("sd.mp4"),
+            video_urls: video_urls(true, true, false),
+            ..Default::default()
+        };
+        item.select_quality(Quality::HD);
+        assert_eq!(item.quality, "HD");
+        assert_eq!(item.video_url, "hd.mp4");
+        item.select_quality(Quality::LQ);
+        assert_eq!(item.quality, "HD");
+    }
+
     #[test]
     fn test_new_pagination() {
         let pagination = Pagination::new();
@@ -130,11 +305,38 @@ mod tests {
         assert_eq!(pagination.page_size(), 15);
     }
 
+    #[test]
+    fn test_total_pages_custom_page_size() {
+        let pagination = Pagination {
+            search: SearchRequest::default(),
+            total: 120,
+            offset: 0,
+            page_size: 50,
+            items: vec![],
+        };
+        assert_eq!(pagination.total_pages(), 3);
+    }
+
+    #[test]
+    fn test_show_with_page_size_zero() {
+        let mut pagination = Pagination::new();
+        let results = SearchResults {
+            total: 20,
+            items: vec![],
+        };
+        pagination.show(SearchRequest::default(), 0, 0, results);
+        assert_eq!(pagination.page_size(), 1);
+        assert_eq!(pagination.total_pages(), 20);
+        assert_eq!(pagination.current_page(), 1);
+    }
+
     #[test]
     fn test_total_pages_empty() {
         let pagination = Pagination {
+            search: SearchRequest::default(),
             total: 0,
             offset: 0,
+            page_size: 15,
             items: vec![],
         };
         assert_eq!(pagination.total_pages(), 0);
@@ -143,8 +345,10 @@ mod tests {
     #[test]
     fn test_total_pages_single_page() {
         let pagination = Pagination {
+            search: SearchRequest::default(),
             total: 15,
             offset: 0,
+            page_size: 15,
             items: vec![],
         };
         assert_eq!(pagination.total_pages(), 1);
@@ -153,8 +357,10 @@ mod tests {
     #[test]
     fn test_total_pages_multiple_pages() {
         let pagination = Pagination {
+            search: SearchRequest::default(),
             total: 30,
             offset: 0,
+            page_size: 15,
             items: vec![],
         };
         assert_eq!(pagination.total_pages(), 2);
@@ -163,8 +369,10 @@ mod tests {
     #[test]
     fn test_total_pages_partial_page() {
         let pagination = Pagination {
+            search: SearchRequest::default(),
             total: 16,
             offset: 0,
+            page_size: 15,
             items: vec![],
         };
         assert_eq!(pagination.total_pages(), 2);
@@ -173,8 +381,10 @@ mod tests {
     #[test]
     fn test_has_next_page_no_items() {
         let pagination = Pagination {
+            search: SearchRequest::default(),
             total: 0,
             offset: 0,
+            page_size: 15,
             items: vec![],
         };
         assert!(!pagination.has_next_page());
@@ -183,8 +393,10 @@ mod tests {
     #[test]
     fn test_has_next_page_single_page() {
         let pagination = Pagination {
+            search: SearchRequest::default(),
             total: 15,
             offset: 0,
+            page_size: 15,
             items: vec![],
         };
         assert!(!pagination.has_next_page());
@@ -193,8 +405,10 @@ mod tests {
     #[test]
     fn test_has_next_page_multiple_pages() {
         let pagination = Pagination {
+            search: SearchRequest::default(),
             total: 30,
             offset: 0,
+            page_size: 15,
             items: vec![],
         };
         assert!(pagination.has_next_page());
@@ -203,8 +417,10 @@ mod tests {
     #[test]
     fn test_has_next_page_last_page() {
         let pagination = Pagination {
+            search: SearchRequest::default(),
             total: 30,
             offset: 15,
+            page_size: 15,
             items: vec![],
         };
         assert!(!pagination.has_next_page());
@@ -213,8 +429,10 @@ mod tests {
     #[test]
     fn test_has_previous_page_no_items() {
         let pagination = Pagination {
+            search: SearchRequest::default(),
             total: 0,
             offset: 0,
+            page_size: 15,
             items: vec![],
         };
         assert!(!pagination.has_previous_page());
@@ -223,8 +441,10 @@ mod tests {
     #[test]
     fn test_has_previous_page_first_page() {
         let pagination = Pagination {
+            search: SearchRequest::default(),
             total: 30,
             offset: 0,
+            page_size: 15,
             items: vec![],
         };
         assert!(!pagination.has_previous_page());
@@ -233,8 +453,10 @@ mod tests {
     #[test]
     fn test_has_previous_page_middle_page() {
         let pagination = Pagination {
+            search: SearchRequest::default(),
             total: 30,
             offset: 15,
+            page_size: 15,
             items: vec![],
         };
         assert!(pagination.has_previous_page());
@@ -243,8 +465,10 @@ mod tests {
     #[test]
     fn test_current_page_first_page() {
         let pagination = Pagination {
+            search: SearchRequest::default(),
             total: 30,
             offset: 0,
+            page_size: 15,
             items: vec![],
         };
         assert_eq!(pagination.current_page(), 1);
@@ -253,8 +477,10 @@ mod tests {
     #[test]
     fn test_current_page_middle_page() {
         let pagination = Pagination {
+            search: SearchRequest::default(),
             total: 30,
             offset: 15,
+            page_size: 15,
             items: vec![],
         };
         assert_eq!(pagination.current_page(), 2);
@@ -263,8 +489,10 @@ mod tests {
     #[test]
     fn test_next_offset_no_items() {
         let pagination = Pagination {
+            search: SearchRequest::default(),
             total: 0,
             offset: 0,
+            page_size: 15,
             items: vec![],
         };
         assert_eq!(pagination.next_offset(), None);
@@ -273,8 +501,10 @@ mod tests {
     #[test]
     fn test_next_offset_single_page() {
         let pagination = Pagination {
+            search: SearchRequest::default(),
             total: 15,
             offset: 0,
+            page_size: 15,
             items: vec![],
         };
         assert_eq!(pagination.next_offset(), None);
@@ -283,8 +513,10 @@ mod tests {
     #[test]
     fn test_next_offset_multiple_pages() {
         let pagination = Pagination {
+            search: SearchRequest::default(),
             total: 30,
             offset: 0,
+            page_size: 15,
             items: vec![],
         };
         assert_eq!(pagination.next_offset(), Some(15));
@@ -293,8 +525,10 @@ mod tests {
     #[test]
     fn test_next_offset_last_page() {
         let pagination = Pagination {
+            search: SearchRequest::default(),
             total: 30,
             offset: 15,
+            page_size: 15,
             items: vec![],
         };
         assert_eq!(pagination.next_offset(), None);
@@ -303,8 +537,10 @@ mod tests {
     #[test]
     fn test_previous_offset_no_items() {
         let pagination = Pagination {
+            search: SearchRequest::default(),
             total: 0,
             offset: 0,
+            page_size: 15,
             items: vec![],
         };
         assert_eq!(pagination.previous_offset(), None);
@@ -313,8 +549,10 @@ mod tests {
     #[test]
     fn test_previous_offset_first_page() {
         let pagination = Pagination {
+            search: SearchRequest::default(),
             total: 30,
             offset: 0,
+            page_size: 15,
             items: vec![],
         };
         assert_eq!(pagination.previous_offset(), None);
@@ -323,8 +561,10 @@ mod tests {
     #[test]
     fn test_previous_offset_middle_page() {
         let pagination = Pagination {
+            search: SearchRequest::default(),
             total: 30,
             offset: 15,
+            page_size: 15,
             items: vec![],
         };
         assert_eq!(pagination.previous_offset(), Some(0));
@@ -333,8 +573,10 @@ mod tests {
     #[test]
     fn test_items_on_page_full_page() {
         let pagination = Pagination {
+            search: SearchRequest::default(),
             total: 30,
             offset: 0,
+            page_size: 15,
             items: vec![],
         };
         assert_eq!(pagination.items_on_page(), 15);
@@ -343,8 +585,10 @@ mod tests {
     #[test]
     fn test_items_on_page_partial_page() {
         let pagination = Pagination {
+            search: SearchRequest::default(),
             total: 16,
             offset: 15,
+            page_size: 15,
             items: vec![],
         };
         assert_eq!(pagination.items_on_page(), 1);
@@ -353,18 +597,34 @@ mod tests {
     #[test]
     fn test_items_on_page_last_page() {
         let pagination = Pagination {
+            search: SearchRequest::default(),
             total: 30,
             offset: 15,
+            page_size: 15,
             items: vec![],
         };
         assert_eq!(pagination.items_on_page(), 15);
     }
 
+    #[test]
+    fn test_items_on_page_offset_beyond_total() {
+        let pagination = Pagination {
+            search: SearchRequest::default(),
+            total: 15,
+            offset: 30,
+            page_size: 15,
+            items: vec![],
+        };
+        assert_eq!(pagination.items_on_page(), 0);
+    }
+
     #[test]
     fn test_is_valid_valid_offset() {
         let pagination = Pagination {
+            search: SearchRequest::default(),
             total: 30,
             offset: 15,
+            page_size: 15,
             items: vec![],
         };
         assert!(pagination.is_valid());
@@ -373,8 +633,10 @@ mod tests {
     #[test]
     fn test_is_valid_invalid_offset() {
         let pagination = Pagination {
+            search: SearchRequest::default(),
             total: 15,
             offset: 20,
+            page_size: 15,
             items: vec![],
         };
         assert!(!pagination.is_valid());
@@ -383,8 +645,10 @@ mod tests {
     #[test]
     fn test_item_range_full_page() {
         let pagination = Pagination {
+            search: SearchRequest::default(),
             total: 30,
             offset: 0,
+            page_size: 15,
             items: vec![],
         };
         assert_eq!(pagination.item_range(), (0, 15));
@@ -393,8 +657,10 @@ mod tests {
     #[test]
     fn test_item_range_partial_page() {
         let pagination = Pagination {
+            search: SearchRequest::default(),
             total: 16,
             offset: 15,
+            page_size: 15,
             items: vec![],
         };
         assert_eq!(pagination.item_range(), (15, 16));
@@ -403,8 +669,10 @@ mod tests {
     #[test]
     fn test_info_empty() {
         let pagination = Pagination {
+            search: SearchRequest::default(),
             total: 0,
             offset: 0,
+            page_size: 15,
             items: vec![],
         };
         assert_eq!(pagination.info(), "Page 1/0 (Items 1-0 of 0)");
@@ -413,8 +681,10 @@ mod tests {
     #[test]
     fn test_info_single_page() {
         let pagination = Pagination {
+            search: SearchRequest::default(),
             total: 15,
             offset: 0,
+            page_size: 15,
             items: vec![],
         };
         assert_eq!(pagination.info(), "Page 1/1 (Items 1-15 of 15)");
@@ -423,10 +693,79 @@ mod tests {
     #[test]
     fn test_info_multiple_pages() {
         let pagination = Pagination {
+            search: SearchRequest::default(),
             total: 30,
             offset: 15,
+            page_size: 15,
             items: vec![],
         };
         assert_eq!(pagination.info(), "Page 2/2 (Items 16-30 of 30)");
     }
+
+    #[test]
+    fn test_last_offset() {
+        let pagination = Pagination {
+            search: SearchRequest::default(),
+            total: 31,
+            offset: 0,
+            page_size: 15,
+            items: vec![],
+        };
+        assert_eq!(pagination.last_offset(), 30);
+    }
+
+    #[test]
+    fn test_last_offset_no_items() {
+        let pagination = Pagination::new();
+        assert_eq!(pagination.last_offset(), 0);
+    }
+
+    #[test]
+    fn test_offset_for_page() {
+        let pagination = Pagination::new();
+        assert_eq!(pagination.offset_for_page(1), 0);
+        assert_eq!(pagination.offset_for_page(3), 30);
+    }
+
+    #[test]
+    fn test_page_numbers_no_items() {
+        let pagination = Pagination::new();
+        assert_eq!(pagination.page_numbers(5), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn test_page_numbers_fewer_pages_than_count() {
+        let pagination = Pagination {
+            search: SearchRequest::default(),
+            total: 30,
+            offset: 0,
+            page_size: 15,
+            items: vec![],
+        };
+        assert_eq!(pagination.page_numbers(5), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_page_numbers_centered() {
+        let pagination = Pagination {
+            search: SearchRequest::default(),
+            total: 150,
+            offset: 75,
+            page_size: 15,
+            items: vec![],
+        };
+        assert_eq!(pagination.page_numbers(5), vec![4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn test_page_numbers_at_the_end() {
+        let pagination = Pagination {
+            search: SearchRequest::default(),
+            total: 150,
+            offset: 135,
+            page_size: 15,
+            items: vec![],
+        };
+        assert_eq!(pagination.page_numbers(5), vec![6, 7, 8, 9, 10]);
+    }
 }
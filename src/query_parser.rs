@@ -0,0 +1,168 @@
+use mediathekviewweb::models::{Query, QueryField};
+use std::time::Duration;
+
+/// A search string split into the parts of MediathekViewWeb's advanced
+/// search syntax:
+///
+/// - `!ard` searches the channel
+/// - `#tatort` searches the topic
+/// - `+fall` searches the title
+/// - `*berlin` searches the description
+/// - `>60` / `<10` limit the duration in minutes
+///
+/// A comma inside a selector stands for a space (`#sturm,der,liebe`),
+/// every other word is searched in topic and title.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ParsedQuery {
+    pub channels: Vec<String>,
+    pub topics: Vec<String>,
+    pub titles: Vec<String>,
+    pub descriptions: Vec<String>,
+    pub text: Vec<String>,
+    pub duration_min: Option<Duration>,
+    pub duration_max: Option<Duration>,
+}
+
+impl ParsedQuery {
+    /// Parses a search string, words that aren't valid selectors
+    /// (like `>abc`) are treated as plain text
+    pub fn parse(input: &str) -> Self {
+        let mut parsed = ParsedQuery::default();
+        for word in input.split_whitespace() {
+            let (prefix, rest) = word.split_at(word.chars().next().map_or(0, char::len_utf8));
+            let term = rest.replace(',', " ").trim().to_string();
+            match prefix {
+                "!" | "#" | "+" | "*" if term.is_empty() => {}
+                "!" => parsed.channels.push(term),
+                "#" => parsed.topics.push(term),
+                "+" => parsed.titles.push(term),
+                "*" => parsed.descriptions.push(term),
+                ">" | "<" => match rest.parse::<u64>() {
+                    Ok(minutes) if prefix == ">" => {
+                        parsed.duration_min = Some(Duration::from_secs(minutes * 60))
+                    }
+                    Ok(minutes) => parsed.duration_max = Some(Duration::from_secs(minutes * 60)),
+                    Err(_) => parsed.text.push(word.to_string()),
+                },
+                _ => parsed.text.push(word.to_string()),
+            }
+        }
+        parsed
+    }
+
+    /// Converts the parsed parts into the queries sent to the API.
+    ///
+    /// Every selector becomes its own query, the server treats queries on
+    /// the same field as alternatives (`!ard !ndr` finds both channels).
+    /// Plain words are combined into a single query on topic and title.
+    pub fn queries(&self) -> Vec<Query> {
+        let selectors = [
+            (QueryField::Channel, &self.channels),
+            (QueryField::Topic, &self.topics),
+            (QueryField::Title, &self.titles),
+            (QueryField::Description, &self.descriptions),
+        ];
+        let mut queries: Vec<Query> = selectors
+            .into_iter()
+            .flat_map(|(field, terms)| {
+                terms.iter().map(move |term| Query {
+                    fields: vec![field],
+                    query: term.clone(),
+                })
+            })
+            .collect();
+        if !self.text.is_empty() {
+            queries.push(Query {
+                fields: vec![QueryField::Topic, QueryField::Title],
+                query: self.text.join(" "),
+            });
+        }
+        queries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(fields: &[QueryField], query: &str) -> Query {
+        Query {
+            fields: fields.to_vec(),
+            query: String::from(query),
+        }
+    }
+
+    #[test]
+    fn test_parse_plain_text() {
+        let parsed = ParsedQuery::parse("tatort  münster");
+        assert_eq!(parsed.text, vec!["tatort", "münster"]);
+        assert_eq!(
+            parsed.queries(),
+            vec![query(
+                &[QueryField::Topic, QueryField::Title],
+                "tatort münster"
+            )]
+        );
+    }
+
+    #[test]
+    fn test_parse_channel() {
+        let parsed = ParsedQuery::parse("!ARD");
+        assert_eq!(parsed.channels, vec!["ARD"]);
+        assert_eq!(parsed.queries(), vec![query(&[QueryField::Channel], "ARD")]);
+    }
+
+    #[test]
+    fn test_parse_topic_with_commas() {
+        let parsed = ParsedQuery::parse("#sturm,der,liebe");
+        assert_eq!(parsed.topics, vec!["sturm der liebe"]);
+    }
+
+    #[test]
+    fn test_parse_title_and_description() {
+        let parsed = ParsedQuery::parse("+gebärdensprache *diane,kruger");
+        assert_eq!(parsed.titles, vec!["gebärdensprache"]);
+        assert_eq!(parsed.descriptions, vec!["diane kruger"]);
+    }
+
+    #[test]
+    fn test_parse_durations() {
+        let parsed = ParsedQuery::parse(">60 <120");
+        assert_eq!(parsed.duration_min, Some(Duration::from_secs(60 * 60)));
+        assert_eq!(parsed.duration_max, Some(Duration::from_secs(120 * 60)));
+        assert!(parsed.queries().is_empty());
+    }
+
+    #[test]
+    fn test_parse_invalid_duration_is_text() {
+        let parsed = ParsedQuery::parse(">abc <");
+        assert_eq!(parsed.duration_min, None);
+        assert_eq!(parsed.duration_max, None);
+        assert_eq!(parsed.text, vec![">abc", "<"]);
+    }
+
+    #[test]
+    fn test_parse_empty_selectors_are_ignored() {
+        assert_eq!(ParsedQuery::parse("! # + *"), ParsedQuery::default());
+    }
+
+    #[test]
+    fn test_parse_combined() {
+        let parsed = ParsedQuery::parse("!ard !ndr #rote,rosen folge >40");
+        assert_eq!(
+            parsed.queries(),
+            vec![
+                query(&[QueryField::Channel], "ard"),
+                query(&[QueryField::Channel], "ndr"),
+                query(&[QueryField::Topic], "rote rosen"),
+                query(&[QueryField::Topic, QueryField::Title], "folge"),
+            ]
+        );
+        assert_eq!(parsed.duration_min, Some(Duration::from_secs(40 * 60)));
+    }
+
+    #[test]
+    fn test_parse_empty() {
+        assert_eq!(ParsedQuery::parse("   "), ParsedQuery::default());
+    }
+}
@@ -0,0 +1,252 @@
+use reqwest::header::{
+    HeaderMap, ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, ETAG, LAST_MODIFIED,
+};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Validators of a partially downloaded file, stored next to the `.part` file
+/// so a later attempt can tell whether the remote file is still the same
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PartialMeta {
+    pub url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub total: Option<u64>,
+}
+
+/// What the server tells about a resource, taken from the response headers
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RemoteInfo {
+    pub accept_ranges: bool,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub content_length: Option<u64>,
+}
+
+impl RemoteInfo {
+    /// Reads the relevant headers of a HEAD or GET response
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let header = |name| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        RemoteInfo {
+            accept_ranges: header(ACCEPT_RANGES)
+                .map(|value| value.split(',').any(|unit| unit.trim() == "bytes"))
+                .unwrap_or(false),
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+            content_length: header(CONTENT_LENGTH).and_then(|value| value.parse().ok()),
+        }
+    }
+
+    /// Gets the value for an `If-Range` header, preferring the strong `ETag`
+    pub fn if_range(&self) -> Option<&str> {
+        self.etag
+            .as_deref()
+            .filter(|etag| !etag.starts_with("W/"))
+            .or(self.last_modified.as_deref())
+    }
+}
+
+/// Gets the path of the file a download is written to until it is complete
+pub fn part_path(destination: &Path) -> PathBuf {
+    let mut name = destination.file_name().unwrap_or_default().to_os_string();
+    name.push(".part");
+    destination.with_file_name(name)
+}
+
+fn meta_path(destination: &Path) -> PathBuf {
+    let mut name = part_path(destination).into_os_string();
+    name.push(".json");
+    PathBuf::from(name)
+}
+
+/// Loads the validators stored for an unfinished download
+pub async fn load_meta(destination: &Path) -> Option<PartialMeta> {
+    let content = tokio::fs::read(meta_path(destination)).await.ok()?;
+    serde_json::from_slice(&content).ok()
+}
+
+/// Stores the validators of an unfinished download
+pub async fn save_meta(destination: &Path, meta: &PartialMeta) -> std::io::Result<()> {
+    let content = serde_json::to_vec(meta).map_err(std::io::Error::other)?;
+    tokio::fs::write(meta_path(destination), content).await
+}
+
+/// Removes the stored validators once a download is complete or restarted
+pub async fn remove_meta(destination: &Path) {
+    let _ = tokio::fs::remove_file(meta_path(destination)).await;
+}
+
+/// Decides whether `existing` bytes of a partial file can be kept.
+///
+/// Resuming requires the server to accept byte ranges and the remote file
+/// to be unchanged, judged by `ETag` or `Last-Modified` (in that order).
+/// Without any validator the size has to match instead.
+pub fn can_resume(existing: u64, stored: &PartialMeta, remote: &RemoteInfo) -> bool {
+    if existing == 0 || !remote.accept_ranges {
+        return false;
+    }
+    if let Some(total) = remote.content_length.or(stored.total) {
+        if existing >= total {
+            return false;
+        }
+    }
+    match (&stored.etag, &remote.etag) {
+        (Some(stored), Some(remote)) => return stored == remote,
+        (Some(_), None) => return false,
+        _ => {}
+    }
+    match (&stored.last_modified, &remote.last_modified) {
+        (Some(stored), Some(remote)) => return stored == remote,
+        (Some(_), None) => return false,
+        _ => {}
+    }
+    stored.total.is_some() && stored.total == remote.content_length
+}
+
+/// Parses a `Content-Range: bytes start-end/total` header into
+/// the start offset and the complete length (if known)
+pub fn parse_content_range(value: &str) -> Option<(u64, Option<u64>)> {
+    let range = value.trim().strip_prefix("bytes ")?;
+    let (span, total) = range.split_once('/')?;
+    let (start, _end) = span.split_once('-')?;
+    let total = match total.trim() {
+        "*" => None,
+        total => Some(total.parse().ok()?),
+    };
+    Some((start.trim().parse().ok()?, total))
+}
+
+/// Reads the `Content-Range` header of a partial response
+pub fn content_range(headers: &HeaderMap) -> Option<(u64, Option<u64>)> {
+    headers
+        .get(CONTENT_RANGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_content_range)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stored(etag: Option<&str>, last_modified: Option<&str>, total: Option<u64>) -> PartialMeta {
+        PartialMeta {
+            url: String::from("https://example.org/video.mp4"),
+            etag: etag.map(str::to_string),
+            last_modified: last_modified.map(str::to_string),
+            total,
+        }
+    }
+
+    fn remote(etag: Option<&str>, last_modified: Option<&str>, length: Option<u64>) -> RemoteInfo {
+        RemoteInfo {
+            accept_ranges: true,
+            etag: etag.map(str::to_string),
+            last_modified: last_modified.map(str::to_string),
+            content_length: length,
+        }
+    }
+
+    #[test]
+    fn test_part_path() {
+        assert_eq!(
+            part_path(Path::new("/tmp/video.mp4")),
+            PathBuf::from("/tmp/video.mp4.part")
+        );
+    }
+
+    #[test]
+    fn test_can_resume_matching_etag() {
+        assert!(can_resume(
+            100,
+            &stored(Some("\"abc\""), None, Some(1000)),
+            &remote(Some("\"abc\""), None, Some(1000))
+        ));
+    }
+
+    #[test]
+    fn test_can_resume_changed_etag() {
+        assert!(!can_resume(
+            100,
+            &stored(Some("\"abc\""), None, Some(1000)),
+            &remote(Some("\"def\""), None, Some(1000))
+        ));
+    }
+
+    #[test]
+    fn test_can_resume_changed_last_modified() {
+        assert!(!can_resume(
+            100,
+            &stored(None, Some("Mon, 01 Jan 2024 00:00:00 GMT"), Some(1000)),
+            &remote(None, Some("Tue, 02 Jan 2024 00:00:00 GMT"), Some(1000))
+        ));
+    }
+
+    #[test]
+    fn test_can_resume_matching_last_modified() {
+        assert!(can_resume(
+            100,
+            &stored(None, Some("Mon, 01 Jan 2024 00:00:00 GMT"), Some(1000)),
+            &remote(None, Some("Mon, 01 Jan 2024 00:00:00 GMT"), Some(1000))
+        ));
+    }
+
+    #[test]
+    fn test_can_resume_without_ranges() {
+        let mut remote = remote(Some("\"abc\""), None, Some(1000));
+        remote.accept_ranges = false;
+        assert!(!can_resume(
+            100,
+            &stored(Some("\"abc\""), None, Some(1000)),
+            &remote
+        ));
+    }
+
+    #[test]
+    fn test_can_resume_without_validators_uses_size() {
+        assert!(can_resume(
+            100,
+            &stored(None, None, Some(1000)),
+            &remote(None, None, Some(1000))
+        ));
+        assert!(!can_resume(
+            100,
+            &stored(None, None, Some(1000)),
+            &remote(None, None, Some(2000))
+        ));
+    }
+
+    #[test]
+    fn test_can_resume_empty_or_complete_part() {
+        let meta = stored(Some("\"abc\""), None, Some(1000));
+        let remote = remote(Some("\"abc\""), None, Some(1000));
+        assert!(!can_resume(0, &meta, &remote));
+        assert!(!can_resume(1000, &meta, &remote));
+    }
+
+    #[test]
+    fn test_if_range_prefers_strong_etag() {
+        let info = remote(Some("\"abc\""), Some("Mon, 01 Jan 2024 00:00:00 GMT"), None);
+        assert_eq!(info.if_range(), Some("\"abc\""));
+        let info = remote(
+            Some("W/\"abc\""),
+            Some("Mon, 01 Jan 2024 00:00:00 GMT"),
+            None,
+        );
+        assert_eq!(info.if_range(), Some("Mon, 01 Jan 2024 00:00:00 GMT"));
+    }
+
+    #[test]
+    fn test_parse_content_range() {
+        assert_eq!(
+            parse_content_range("bytes 100-999/1000"),
+            Some((100, Some(1000)))
+        );
+        assert_eq!(parse_content_range("bytes 100-999/*"), Some((100, None)));
+        assert_eq!(parse_content_range("items 1-2/3"), None);
+    }
+}
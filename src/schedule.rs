@@ -0,0 +1,141 @@
+use chrono::{DateTime, NaiveTime, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ScheduleError {
+    #[error("invalid time {0:?}, expected HH:MM")]
+    InvalidTime(String),
+    #[error("unknown time zone {0:?}")]
+    UnknownTimezone(String),
+}
+
+/// Daily time window downloads are restricted to, e.g. the night
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DownloadWindow {
+    pub enabled: bool,
+    /// local time as `HH:MM`
+    pub start: String,
+    pub end: String,
+    /// IANA time zone name like `Europe/Berlin`
+    pub timezone: String,
+}
+
+impl Default for DownloadWindow {
+    fn default() -> Self {
+        DownloadWindow {
+            enabled: false,
+            start: String::from("01:00"),
+            end: String::from("06:00"),
+            timezone: String::from("Europe/Berlin"),
+        }
+    }
+}
+
+impl DownloadWindow {
+    pub fn validate(&self) -> Result<(), ScheduleError> {
+        self.parse().map(|_| ())
+    }
+
+    /// Checks whether downloads may run at `now`.
+    ///
+    /// A window ending before it starts spans midnight, one ending when it
+    /// starts is open all day. A disabled or invalid window never holds
+    /// downloads back.
+    pub fn is_open(&self, now: DateTime<Utc>) -> bool {
+        if !self.enabled {
+            return true;
+        }
+        let Ok((start, end, timezone)) = self.parse() else {
+            return true;
+        };
+        let time = now.with_timezone(&timezone).time();
+        if start < end {
+            start <= time && time < end
+        } else if start > end {
+            start <= time || time < end
+        } else {
+            true
+        }
+    }
+
+    /// Describes the window like `01:00–06:00 Europe/Berlin`
+    pub fn label(&self) -> String {
+        format!("{}–{} {}", self.start, self.end, self.timezone)
+    }
+
+    fn parse(&self) -> Result<(NaiveTime, NaiveTime, Tz), ScheduleError> {
+        let time = |value: &str| {
+            NaiveTime::parse_from_str(value.trim(), "%H:%M")
+                .map_err(|_| ScheduleError::InvalidTime(value.to_string()))
+        };
+        let timezone = self
+            .timezone
+            .trim()
+            .parse::<Tz>()
+            .map_err(|_| ScheduleError::UnknownTimezone(self.timezone.clone()))?;
+        Ok((time(&self.start)?, time(&self.end)?, timezone))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn window(start: &str, end: &str) -> DownloadWindow {
+        DownloadWindow {
+            enabled: true,
+            start: String::from(start),
+            end: String::from(end),
+            ..Default::default()
+        }
+    }
+
+    fn utc(hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, 15, hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn test_window_in_local_time() {
+        // Berlin is one hour ahead of UTC in January
+        let night = window("01:00", "06:00");
+        assert!(!night.is_open(utc(23, 59)));
+        assert!(night.is_open(utc(0, 0)));
+        assert!(night.is_open(utc(4, 59)));
+        assert!(!night.is_open(utc(5, 0)));
+    }
+
+    #[test]
+    fn test_window_across_midnight() {
+        let night = window("22:00", "06:00");
+        assert!(night.is_open(utc(21, 0)));
+        assert!(night.is_open(utc(2, 0)));
+        assert!(!night.is_open(utc(12, 0)));
+    }
+
+    #[test]
+    fn test_disabled_or_full_day_is_always_open() {
+        let mut always = window("03:00", "03:00");
+        assert!(always.is_open(utc(12, 0)));
+        always = window("01:00", "06:00");
+        always.enabled = false;
+        assert!(always.is_open(utc(12, 0)));
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(window("01:00", "06:00").validate().is_ok());
+        assert!(matches!(
+            window("25:00", "06:00").validate(),
+            Err(ScheduleError::InvalidTime(_))
+        ));
+        let mut unknown = window("01:00", "06:00");
+        unknown.timezone = String::from("Europe/Atlantis");
+        assert!(matches!(
+            unknown.validate(),
+            Err(ScheduleError::UnknownTimezone(_))
+        ));
+    }
+}
@@ -0,0 +1,159 @@
+use crate::{
+    filmliste::{FilmlisteBackend, FilmlisteError},
+    pagination::{SearchItem, VideoUrls},
+    query_parser::ParsedQuery,
+    search_logic::{SearchOptions, SearchRequest},
+    settings::{Quality, SearchSource, Settings},
+    MEDOW_USER_AGENT,
+};
+use mediathekviewweb::{models::QueryField, Mediathek};
+
+#[derive(Debug, thiserror::Error)]
+pub enum BackendError {
+    #[error("MediathekViewWeb request failed: {0:?}")]
+    Api(#[from] mediathekviewweb::Error),
+    #[error("offline search failed: {0}")]
+    Filmliste(#[from] FilmlisteError),
+}
+
+/// One page of results together with the number of all matches
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SearchResults {
+    pub total: usize,
+    pub items: Vec<SearchItem>,
+}
+
+/// A source of search results.
+///
+/// Backends get the search as typed by the user together with the options
+/// from the settings and answer with one page of results, the views don't
+/// know where the results come from.
+pub trait SearchBackend {
+    async fn search(
+        &self,
+        search: &SearchRequest,
+        options: &SearchOptions,
+        offset: usize,
+        size: usize,
+    ) -> Result<SearchResults, BackendError>;
+}
+
+/// Gets the backend searches are sent to
+pub fn configured(settings: &Settings) -> ConfiguredBackend {
+    match settings.search_source {
+        SearchSource::Api => ConfiguredBackend::Mediathek(MediathekBackend),
+        SearchSource::Filmliste => ConfiguredBackend::Filmliste(FilmlisteBackend),
+    }
+}
+
+/// The backend chosen in the settings
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConfiguredBackend {
+    Mediathek(MediathekBackend),
+    Filmliste(FilmlisteBackend),
+}
+
+impl SearchBackend for ConfiguredBackend {
+    async fn search(
+        &self,
+        search: &SearchRequest,
+        options: &SearchOptions,
+        offset: usize,
+        size: usize,
+    ) -> Result<SearchResults, BackendError> {
+        match self {
+            ConfiguredBackend::Mediathek(backend) => {
+                backend.search(search, options, offset, size).await
+            }
+            ConfiguredBackend::Filmliste(backend) => {
+                backend.search(search, options, offset, size).await
+            }
+        }
+    }
+}
+
+/// Searches the live MediathekViewWeb API
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MediathekBackend;
+
+impl SearchBackend for MediathekBackend {
+    async fn search(
+        &self,
+        search: &SearchRequest,
+        options: &SearchOptions,
+        offset: usize,
+        size: usize,
+    ) -> Result<SearchResults, BackendError> {
+        let mediathek_client = Mediathek::new(MEDOW_USER_AGENT.try_into().unwrap())?;
+
+        // Translate the advanced search syntax (`!channel #topic +title ...`)
+        let parsed = ParsedQuery::parse(&search.query);
+        let mut request = mediathek_client.query_string("", false);
+        for parsed_query in parsed.queries() {
+            request = request.query(parsed_query.fields, parsed_query.query);
+        }
+        // Queries on the same field are alternatives, so this matches any selected channel
+        for channel in &options.channels {
+            request = request.query([QueryField::Channel], channel.clone());
+        }
+        let (duration_min, duration_max) = search.duration_bounds(&parsed);
+        if let Some(duration_min) = duration_min {
+            request = request.duration_min(duration_min);
+        }
+        if let Some(duration_max) = duration_max {
+            request = request.duration_max(duration_max);
+        }
+
+        let search_result = request
+            .include_future(false)
+            .sort_by(options.sort_field)
+            .sort_order(options.sort_order)
+            .size(size)
+            .offset(offset)
+            .await?;
+
+        Ok(SearchResults {
+            total: search_result.query_info.total_results as usize,
+            items: search_result
+                .results
+                .into_iter()
+                .map(|item| search_item(item, &options.quality_order))
+                .collect(),
+        })
+    }
+}
+
+/// Maps an API result to a SearchItem
+pub fn search_item(item: mediathekviewweb::models::Item, quality_order: &[Quality]) -> SearchItem {
+    // Keep all variants and start with the preferred available quality
+    let video_urls = VideoUrls {
+        hd: item.url_video_hd,
+        sd: Some(item.url_video).filter(|url| !url.is_empty()),
+        low: item.url_video_low,
+    };
+    let (quality, video_url) = match video_urls.select(quality_order) {
+        Some((quality, url)) => (quality.to_string(), url.clone()),
+        None => (String::new(), String::new()),
+    };
+
+    // Handle optional fields with defaults
+    let timestamp = crate::utils::timestamp_to_german_datetime(item.timestamp);
+    let duration = crate::utils::format_duration(&item.duration);
+
+    SearchItem {
+        channel: item.channel,
+        title: item.title,
+        topic: item.topic,
+        timestamp,
+        duration,
+        quality,
+        video_url,
+        video_urls,
+        subtitle_url: item.url_subtitle,
+        id: item.id,
+        description: item.description,
+        website_url: item.url_website,
+        size: item.size.map(|size| size as u64),
+        published: item.timestamp,
+    }
+}
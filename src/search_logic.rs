@@ -1,82 +1,347 @@
 use crate::{
+    error, filesize,
     pagination::{Pagination, SearchItem},
-    APP_STATE, MEDOW_USER_AGENT,
+    query_parser::ParsedQuery,
+    save_history,
+    search_backend::{self, BackendError, SearchBackend},
+    settings::{Quality, Settings},
+    APP_STATE,
 };
 use dioxus::prelude::*;
-use mediathekviewweb::Mediathek;
+use mediathekviewweb::models::{SortField, SortOrder};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
 
-pub async fn perform_search(mut pagination: Signal<Pagination>, query: String, offset: usize) {
-    println!("in the search callback with query string {query}");
-    APP_STATE.write().is_loading = true;
-    let mediathek_client = match Mediathek::new(MEDOW_USER_AGENT.try_into().unwrap()) {
-        Ok(client) => client,
-        Err(error) => {
-            APP_STATE.write().error = Some(format!("{error:?}"));
-            APP_STATE.write().is_loading = false;
-            return;
+/// Channels offered by MediathekViewWeb, used for the channel filter
+pub const CHANNELS: [&str; 22] = [
+    "3Sat",
+    "ARD",
+    "ARTE.DE",
+    "ARTE.FR",
+    "BR",
+    "DW",
+    "Funk.net",
+    "HR",
+    "KiKA",
+    "MDR",
+    "NDR",
+    "ORF",
+    "PHOENIX",
+    "Radio Bremen TV",
+    "RBB",
+    "SR",
+    "SRF",
+    "SRF.Podcast",
+    "SWR",
+    "WDR",
+    "ZDF",
+    "ZDF-tivi",
+];
+
+/// Most results "select all" adds to the selection at once
+pub const SELECT_ALL_LIMIT: usize = 500;
+
+// page size used when loading all results of a search
+const FETCH_ALL_PAGE_SIZE: usize = 100;
+
+/// Everything describing a search apart from the requested page
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchRequest {
+    pub query: String,
+    /// minimum duration in minutes, overrides `>` in the query
+    pub duration_min: Option<u64>,
+    /// maximum duration in minutes, overrides `<` in the query
+    pub duration_max: Option<u64>,
+}
+
+impl SearchRequest {
+    /// Gets the duration bounds, preferring the explicit ones
+    /// over those typed into the query
+    pub fn duration_bounds(&self, parsed: &ParsedQuery) -> (Option<Duration>, Option<Duration>) {
+        let minutes = |minutes: u64| Duration::from_secs(minutes * 60);
+        (
+            self.duration_min.map(minutes).or(parsed.duration_min),
+            self.duration_max.map(minutes).or(parsed.duration_max),
+        )
+    }
+}
+
+/// Filters and preferences applied to every search, usually taken from the settings
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchOptions {
+    /// channels the search is restricted to, empty for all channels
+    pub channels: Vec<String>,
+    pub sort_field: SortField,
+    pub sort_order: SortOrder,
+    pub quality_order: Vec<Quality>,
+}
+
+impl SearchOptions {
+    pub fn from_settings(settings: &Settings) -> Self {
+        SearchOptions {
+            channels: settings.channels.clone(),
+            sort_field: settings.sort_field,
+            sort_order: settings.sort_order,
+            quality_order: settings.quality_order.clone(),
         }
-    };
+    }
+}
+
+/// Gets the sort order after clicking a column header: the sorted column
+/// flips its direction, another one starts with the newest, longest or
+/// alphabetically first results
+pub fn toggle_sort(current: (SortField, SortOrder), column: SortField) -> (SortField, SortOrder) {
+    match current {
+        (field, SortOrder::Descending) if field == column => (column, SortOrder::Ascending),
+        (field, SortOrder::Ascending) if field == column => (column, SortOrder::Descending),
+        _ if column == SortField::Channel => (column, SortOrder::Ascending),
+        _ => (column, SortOrder::Descending),
+    }
+}
+
+/// Loads the results of all pages of a search, at most `limit` of them
+pub async fn fetch_all(
+    backend: &impl SearchBackend,
+    search: &SearchRequest,
+    options: &SearchOptions,
+    limit: usize,
+) -> Result<Vec<SearchItem>, BackendError> {
+    let mut items = Vec::new();
+    loop {
+        let size = FETCH_ALL_PAGE_SIZE.min(limit - items.len());
+        let mut page = backend.search(search, options, items.len(), size).await?;
+        let done = page.items.is_empty();
+        items.append(&mut page.items);
+        if done || items.len() >= page.total.min(limit) {
+            return Ok(items);
+        }
+    }
+}
 
-    let query = mediathek_client
-        .query(
-            [
-                mediathekviewweb::models::QueryField::Topic,
-                mediathekviewweb::models::QueryField::Title,
-            ],
-            query,
+/// Runs a search on the configured backend, shows the page in `pagination`
+/// and records the search in the history
+pub async fn perform_search(
+    mut pagination: Signal<Pagination>,
+    search: SearchRequest,
+    offset: usize,
+) {
+    println!("in the search callback with query string {}", search.query);
+    APP_STATE.write().is_loading = true;
+    let (backend, page_size, options) = {
+        let settings = &APP_STATE.read().settings;
+        (
+            search_backend::configured(settings),
+            settings.page_size,
+            SearchOptions::from_settings(settings),
         )
-        .include_future(false)
-        .sort_by(mediathekviewweb::models::SortField::Timestamp)
-        .sort_order(mediathekviewweb::models::SortOrder::Descending)
-        .size(15)
-        .offset(offset);
+    };
 
-    let search_result = match query.await {
+    let results = match backend.search(&search, &options, offset, page_size).await {
         Ok(result) => {
             APP_STATE.write().is_loading = false;
             result
         }
         Err(error) => {
-            APP_STATE.write().error = Some(format!("{error:?}"));
             APP_STATE.write().is_loading = false;
+            error::report_with_retry(error, move || {
+                spawn(perform_search(pagination, search.clone(), offset));
+            });
             return;
         }
     };
 
-    // Map SearchResult to SearchItem
-    let mut search_items: Vec<SearchItem> = search_result
-        .results
-        .into_iter()
-        .map(|item| {
-            // Determine the best video URL available
-            let (video_url, quality) = if !item.url_video.is_empty() {
-                (item.url_video, String::from("SD"))
-            } else if let Some(url) = item.url_video_hd {
-                (url, String::from("HD"))
-            } else {
-                (
-                    item.url_video_low.unwrap_or(String::from("")),
-                    String::from("LQ"),
-                )
-            };
-
-            // Handle optional fields with defaults
-            let timestamp = crate::utils::timestamp_to_german_datetime(item.timestamp);
-            let duration = crate::utils::format_duration(&item.duration);
-
-            SearchItem {
-                selected: false,
-                title: item.title,
-                topic: item.topic,
-                timestamp: timestamp,
-                duration: duration,
-                quality: quality,
-                video_url,
+    // Update the items signal with the new search results
+    let page = {
+        let mut pagination = pagination.write();
+        pagination.show(search.clone(), offset, page_size, results);
+        filesize::probe_all(&pagination.items);
+        pagination.current_page()
+    };
+
+    // Remember the search (and the page) for the history list
+    {
+        let mut state = APP_STATE.write();
+        let limit = state.settings.history_size;
+        let now = chrono::Utc::now().timestamp();
+        state
+            .history
+            .record(&search, &options, offset, page, now, limit);
+    }
+    save_history();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search_backend::SearchResults;
+    use crate::testing::block_on;
+    use std::cell::Cell;
+
+    // answers every search with slices of a fixed result list
+    struct FakeBackend {
+        items: Vec<SearchItem>,
+        requests: Cell<usize>,
+    }
+
+    impl FakeBackend {
+        fn new(count: usize) -> Self {
+            FakeBackend {
+                items: (0..count)
+                    .map(|index| SearchItem {
+                        id: index.to_string(),
+                        title: format!("title {index}"),
+                        ..Default::default()
+                    })
+                    .collect(),
+                requests: Cell::new(0),
             }
-        })
-        .collect();
+        }
+    }
 
-    // Update the items signal with the new search results
-    pagination.write().items.clear();
-    pagination.write().items.append(search_items.as_mut());
+    impl SearchBackend for FakeBackend {
+        async fn search(
+            &self,
+            _search: &SearchRequest,
+            _options: &SearchOptions,
+            offset: usize,
+            size: usize,
+        ) -> Result<SearchResults, BackendError> {
+            self.requests.set(self.requests.get() + 1);
+            Ok(SearchResults {
+                total: self.items.len(),
+                items: self.items.iter().skip(offset).take(size).cloned().collect(),
+            })
+        }
+    }
+
+    fn options() -> SearchOptions {
+        SearchOptions::from_settings(&Settings::default())
+    }
+
+    #[test]
+    fn test_fetch_all_loads_every_page() {
+        let backend = FakeBackend::new(250);
+        let items = block_on(fetch_all(
+            &backend,
+            &SearchRequest::default(),
+            &options(),
+            1000,
+        ))
+        .unwrap();
+        assert_eq!(items.len(), 250);
+        assert_eq!(items[249].id, "249");
+        assert_eq!(backend.requests.get(), 3);
+    }
+
+    #[test]
+    fn test_fetch_all_stops_at_limit() {
+        let backend = FakeBackend::new(250);
+        let items = block_on(fetch_all(
+            &backend,
+            &SearchRequest::default(),
+            &options(),
+            120,
+        ))
+        .unwrap();
+        assert_eq!(items.len(), 120);
+        assert_eq!(backend.requests.get(), 2);
+    }
+
+    #[test]
+    fn test_fetch_all_without_results() {
+        let backend = FakeBackend::new(0);
+        let items = block_on(fetch_all(
+            &backend,
+            &SearchRequest::default(),
+            &options(),
+            100,
+        ))
+        .unwrap();
+        assert!(items.is_empty());
+        assert_eq!(backend.requests.get(), 1);
+    }
+
+    #[test]
+    fn test_backend_page_shown_in_pagination() {
+        let backend = FakeBackend::new(40);
+        let search = SearchRequest {
+            query: String::from("tatort"),
+            ..Default::default()
+        };
+        let results = block_on(backend.search(&search, &options(), 15, 15)).unwrap();
+        let mut pagination = Pagination::new();
+        pagination.show(search.clone(), 15, 15, results);
+        assert_eq!(pagination.search, search);
+        assert_eq!(pagination.total, 40);
+        assert_eq!(pagination.current_page(), 2);
+        assert_eq!(pagination.items.len(), 15);
+        assert_eq!(pagination.items[0].id, "15");
+    }
+
+    #[test]
+    fn test_toggle_sort_same_column_flips() {
+        assert_eq!(
+            toggle_sort(
+                (SortField::Timestamp, SortOrder::Descending),
+                SortField::Timestamp
+            ),
+            (SortField::Timestamp, SortOrder::Ascending)
+        );
+        assert_eq!(
+            toggle_sort(
+                (SortField::Timestamp, SortOrder::Ascending),
+                SortField::Timestamp
+            ),
+            (SortField::Timestamp, SortOrder::Descending)
+        );
+    }
+
+    #[test]
+    fn test_toggle_sort_new_column() {
+        assert_eq!(
+            toggle_sort(
+                (SortField::Timestamp, SortOrder::Ascending),
+                SortField::Duration
+            ),
+            (SortField::Duration, SortOrder::Descending)
+        );
+        assert_eq!(
+            toggle_sort(
+                (SortField::Timestamp, SortOrder::Descending),
+                SortField::Channel
+            ),
+            (SortField::Channel, SortOrder::Ascending)
+        );
+    }
+
+    #[test]
+    fn test_duration_bounds_from_query() {
+        let search = SearchRequest {
+            query: String::from("tatort >60"),
+            ..Default::default()
+        };
+        let parsed = ParsedQuery::parse(&search.query);
+        assert_eq!(
+            search.duration_bounds(&parsed),
+            (Some(Duration::from_secs(3600)), None)
+        );
+    }
+
+    #[test]
+    fn test_duration_bounds_explicit_override_query() {
+        let search = SearchRequest {
+            query: String::from("tatort >60 <120"),
+            duration_min: Some(5),
+            duration_max: None,
+        };
+        let parsed = ParsedQuery::parse(&search.query);
+        assert_eq!(
+            search.duration_bounds(&parsed),
+            (
+                Some(Duration::from_secs(300)),
+                Some(Duration::from_secs(7200))
+            )
+        );
+    }
 }
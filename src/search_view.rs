@@ -1,9 +1,40 @@
-use crate::{pagination::Pagination, search_logic, APP_STATE};
+use crate::{
+    disk,
+    download::{self, Duplicate, Duplicates},
+    download_log::Episode,
+    error,
+    filesize::{self, FileSize},
+    history::{HistoryEntry, SearchHistory},
+    pagination::{Pagination, SearchItem},
+    save_history, save_settings, search_backend,
+    search_logic::{self, SearchOptions, SearchRequest},
+    selection::Selection,
+    settings::Quality,
+    utils, View, APP_STATE, DOWNLOADS,
+};
 use dioxus::prelude::*;
+use mediathekviewweb::models::{SortField, SortOrder};
 
 #[component]
-pub fn header_bar(pagination: Signal<Pagination>) -> Element {
-    let mut searchstring = use_signal(|| String::new());
+pub fn header_bar(
+    pagination: Signal<Pagination>,
+    selection: Signal<Selection>,
+    show_review: Signal<bool>,
+) -> Element {
+    // start with the last search so it survives switching views
+    let mut searchstring = use_signal(|| pagination.read().search.query.clone());
+    let mut duration_min = use_signal(|| pagination.read().search.duration_min);
+    let mut duration_max = use_signal(|| pagination.read().search.duration_max);
+    let mut show_history = use_signal(|| false);
+    let duration_invalid =
+        matches!((duration_min(), duration_max()), (Some(min), Some(max)) if min > max);
+
+    let search = move || SearchRequest {
+        query: searchstring(),
+        duration_min: duration_min(),
+        duration_max: duration_max(),
+    };
+
     rsx! {
         header {
             class: "sticky-header",
@@ -13,39 +44,108 @@ pub fn header_bar(pagination: Signal<Pagination>) -> Element {
                 nav {
                     ul {
                         li {
+                            class: "search-box",
                             input {
                                 r#type: "text",
-                                placeholder: "Search...",
+                                placeholder: "Search... (!channel #topic +title *description >min <min)",
                                 class: "input search-input",
-                                oninput: move |event_data| {
-                                    let value = event_data.value();
-                                    searchstring.set(event_data.value())
-                                },
+                                value: "{searchstring}",
+                                oninput: move |event_data| searchstring.set(event_data.value()),
+                                onfocus: move |_| show_history.set(true),
+                                onblur: move |_| show_history.set(false),
                                 onkeydown: move |event_data| async move {
                                     if event_data.key() == Key::Enter {
-                                        search_logic::perform_search(pagination, searchstring(), 0).await;
-                                    } else {
-                                        ()
+                                        show_history.set(false);
+                                        search_logic::perform_search(pagination, search(), 0).await;
                                     }
                                 }
                             }
+                            if show_history() && !APP_STATE.read().history.entries.is_empty() {
+                                search_history {
+                                    pagination,
+                                    searchstring,
+                                    duration_min,
+                                    duration_max,
+                                    show_history,
+                                }
+                            }
+                        }
+                        li {
+                            input {
+                                r#type: "number",
+                                min: 0,
+                                placeholder: "min",
+                                title: "Minimum duration in minutes",
+                                class: "input duration-input",
+                                "aria-invalid": duration_invalid,
+                                value: duration_min().map(|minutes| minutes.to_string()).unwrap_or_default(),
+                                oninput: move |event_data| duration_min.set(event_data.value().parse().ok()),
+                            }
+                        }
+                        li { "–" }
+                        li {
+                            input {
+                                r#type: "number",
+                                min: 0,
+                                placeholder: "max",
+                                title: "Maximum duration in minutes",
+                                class: "input duration-input",
+                                "aria-invalid": duration_invalid,
+                                value: duration_max().map(|minutes| minutes.to_string()).unwrap_or_default(),
+                                oninput: move |event_data| duration_max.set(event_data.value().parse().ok()),
+                            }
                         }
                         li {
                             button {
                                 class: "button",
                                 onclick: move |_| {
-                                    search_logic::perform_search(pagination, searchstring(), 0)
+                                    search_logic::perform_search(pagination, search(), 0)
                                 },
                                 "Search",
                             }
                         }
+                        li { channel_filter { pagination } }
+                        li {
+                            button {
+                                class: "button",
+                                disabled: selection.read().is_empty(),
+                                onclick: move |_| show_review.set(true),
+                                "Download ({selection.read().len()})",
+                            }
+                        }
+                        li {
+                            label {
+                                title: "Also download subtitles if available",
+                                input {
+                                    r#type: "checkbox",
+                                    checked: APP_STATE.read().settings.download_subtitles,
+                                    oninput: move |e| toggle_subtitles(e.checked()),
+                                }
+                                "Subtitles"
+                            }
+                        }
                     }
                     ul {
                         li {
-                            button { class: "button", "Downloads" }
+                            button {
+                                class: "button",
+                                onclick: move |_| APP_STATE.write().view = View::Download,
+                                "Downloads",
+                            }
+                        }
+                        li {
+                            button {
+                                class: "button",
+                                onclick: move |_| APP_STATE.write().view = View::Subscriptions,
+                                "Subscriptions",
+                            }
                         }
                         li {
-                            button { class: "button", "Settings" }
+                            button {
+                                class: "button",
+                                onclick: move |_| APP_STATE.write().view = View::Settings,
+                                "Settings",
+                            }
                         }
                     }
                 }
@@ -54,10 +154,196 @@ pub fn header_bar(pagination: Signal<Pagination>) -> Element {
     }
 }
 
+// remembers whether subtitles are fetched along with the videos
+fn toggle_subtitles(checked: bool) {
+    APP_STATE.write().settings.download_subtitles = checked;
+    save_settings();
+}
+
+// puts back the channels and sort order a history entry was searched with
+fn restore_filters(entry: &HistoryEntry) {
+    {
+        let settings = &mut APP_STATE.write().settings;
+        settings.channels = entry.channels.clone();
+        settings.sort_field = entry.sort_field;
+        settings.sort_order = entry.sort_order;
+    }
+    save_settings();
+}
+
+// saves the history after pinning or deleting an entry
+fn update_history(change: impl FnOnce(&mut SearchHistory)) {
+    change(&mut APP_STATE.write().history);
+    save_history();
+}
+
 #[component]
-fn media_table(pagination: Signal<Pagination>) -> Element {
-    // Create a signal for the header checkbox state
-    let mut header_selected = use_signal(|| false);
+fn search_history(
+    pagination: Signal<Pagination>,
+    searchstring: Signal<String>,
+    duration_min: Signal<Option<u64>>,
+    duration_max: Signal<Option<u64>>,
+    show_history: Signal<bool>,
+) -> Element {
+    let entries: Vec<(usize, HistoryEntry)> = APP_STATE
+        .read()
+        .history
+        .sorted()
+        .into_iter()
+        .map(|(index, entry)| (index, entry.clone()))
+        .collect();
+
+    // mousedown fires before the input loses focus and closes the list
+    rsx! {
+        ul {
+            class: "search-history",
+            for (index, entry) in entries {
+                li {
+                    key: "{index}",
+                    span {
+                        class: "search-history-label",
+                        title: "Repeat this search",
+                        onmousedown: {
+                            let entry = entry.clone();
+                            move |event: MouseEvent| {
+                                event.prevent_default();
+                                searchstring.set(entry.search.query.clone());
+                                duration_min.set(entry.search.duration_min);
+                                duration_max.set(entry.search.duration_max);
+                                show_history.set(false);
+                                restore_filters(&entry);
+                                spawn(search_logic::perform_search(
+                                    pagination,
+                                    entry.search.clone(),
+                                    entry.offset,
+                                ));
+                            }
+                        },
+                        "{entry.label()}"
+                    }
+                    button {
+                        class: "button outline secondary",
+                        title: if entry.pinned { "Unpin" } else { "Pin" },
+                        onmousedown: move |event: MouseEvent| {
+                            event.prevent_default();
+                            update_history(|history| history.toggle_pin(index));
+                        },
+                        if entry.pinned { "★" } else { "☆" }
+                    }
+                    button {
+                        class: "button outline secondary",
+                        title: "Delete",
+                        onmousedown: move |event: MouseEvent| {
+                            event.prevent_default();
+                            update_history(|history| history.remove(index));
+                        },
+                        "✕"
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[component]
+fn channel_filter(pagination: Signal<Pagination>) -> Element {
+    let selected = APP_STATE.read().settings.channels.clone();
+    let summary = match selected.len() {
+        0 => String::from("All channels"),
+        1 => selected[0].clone(),
+        count => format!("{count} channels"),
+    };
+
+    // remembers the selection in the settings and repeats the current search
+    let toggle = move |channel: &str, checked: bool| {
+        {
+            let mut state = APP_STATE.write();
+            let channels = &mut state.settings.channels;
+            channels.retain(|selected| selected != channel);
+            if checked {
+                channels.push(channel.to_string());
+            }
+        }
+        save_settings();
+        if !pagination.read().items.is_empty() {
+            let search = pagination.read().search.clone();
+            spawn(search_logic::perform_search(pagination, search, 0));
+        }
+    };
+
+    rsx! {
+        details {
+            class: "dropdown channel-filter",
+            summary { "{summary}" }
+            ul {
+                for channel in search_logic::CHANNELS {
+                    li {
+                        label {
+                            input {
+                                r#type: "checkbox",
+                                checked: selected.iter().any(|selected| selected == channel),
+                                oninput: move |e| toggle(channel, e.checked()),
+                            }
+                            "{channel}"
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// column header that sorts the results on the server when clicked
+#[component]
+fn sort_header(pagination: Signal<Pagination>, column: SortField, label: &'static str) -> Element {
+    let (field, order) = {
+        let settings = &APP_STATE.read().settings;
+        (settings.sort_field, settings.sort_order)
+    };
+    let indicator = match order {
+        _ if field != column => "",
+        SortOrder::Ascending => " ▲",
+        SortOrder::Descending => " ▼",
+    };
+
+    // remembers the order in the settings and loads the first page again
+    let sort = move |_| {
+        {
+            let mut state = APP_STATE.write();
+            let current = (state.settings.sort_field, state.settings.sort_order);
+            (state.settings.sort_field, state.settings.sort_order) =
+                search_logic::toggle_sort(current, column);
+        }
+        save_settings();
+        if !pagination.read().items.is_empty() {
+            let search = pagination.read().search.clone();
+            spawn(search_logic::perform_search(pagination, search, 0));
+        }
+    };
+
+    rsx! {
+        th {
+            scope: "col",
+            class: "sortable",
+            "aria-sort": match order {
+                _ if field != column => "none",
+                SortOrder::Ascending => "ascending",
+                SortOrder::Descending => "descending",
+            },
+            onclick: sort,
+            "{label}{indicator}"
+        }
+    }
+}
+
+#[component]
+fn media_table(
+    pagination: Signal<Pagination>,
+    selection: Signal<Selection>,
+    detail: Signal<Option<SearchItem>>,
+) -> Element {
+    // the header checkbox reflects whether the whole page is selected
+    let page_selected = selection.read().contains_all(&pagination.read().items);
     rsx! {
         table {
             thead {
@@ -66,55 +352,87 @@ fn media_table(pagination: Signal<Pagination>) -> Element {
                         scope: "col",
                         input {
                             r#type: "checkbox",
-                            checked: header_selected(),
+                            checked: page_selected,
                             oninput: move |e| {
-                                let checked = e.checked();
-                                header_selected.set(checked);
-                                // Update all items' selected state
-                                for item in pagination.write().items.iter_mut() {
-                                    item.selected = checked;
+                                let items = &pagination.read().items;
+                                let mut selection = selection.write();
+                                for item in items {
+                                    selection.set(item, e.checked());
                                 }
                             }
                         }
                     }
+                    sort_header { pagination, column: SortField::Channel, label: "channel" }
                     th { scope: "col", "title" }
                     th { scope: "col", "topic" }
-                    th { scope: "col", "timestamp" }
-                    th { scope: "col", "duration" }
+                    sort_header { pagination, column: SortField::Timestamp, label: "timestamp" }
+                    sort_header { pagination, column: SortField::Duration, label: "duration" }
                     th { scope: "col", "quality" }
+                    th { scope: "col", "size" }
                 }
             }
             tbody {
                 // Render each item as a table row
                 for (index, item) in pagination.read().items.iter().enumerate() {
                     tr {
+                        class: "result-row",
+                        onclick: {
+                            let item = item.clone();
+                            move |_| detail.set(Some(item.clone()))
+                        },
                         td {
+                            onclick: move |event| event.stop_propagation(),
                             input {
                                 r#type: "checkbox",
-                                checked: item.selected,
-                                oninput: move |e| {
-                                    let checked = e.checked();
-                                    // Update the specific item by index
-                                    pagination.write().items[index].selected = checked;
-
-                                    // Update header checkbox based on all items
-                                    let all_selected = pagination.read().items.iter().all(|item| item.selected);
-                                    let any_selected = pagination.read().items.iter().any(|item| item.selected);
-
-                                    if all_selected {
-                                        header_selected.set(true);
-                                    } else if !any_selected {
-                                        header_selected.set(false);
-                                    }
+                                checked: selection.read().contains(item),
+                                oninput: {
+                                    let item = item.clone();
+                                    move |e: FormEvent| selection.write().set(&item, e.checked())
                                 }
                             }
 
                         }
-                        td { "{item.title}" }
+                        td { "{item.channel}" }
+                        td {
+                            "{item.title}"
+                            if item.subtitle_url.is_some() {
+                                small { class: "subtitle-badge", title: "Subtitles available", "CC" }
+                            }
+                            if let Some(record) = APP_STATE.read().download_log.finished(&Episode::from_item(item)) {
+                                small {
+                                    class: "downloaded-badge",
+                                    title: "Downloaded to {record.destination.display()}",
+                                    "✓"
+                                }
+                            }
+                        }
                         td { "{item.topic}" }
                         td { "{item.timestamp}" }
                         td { "{item.duration}" }
-                        td { "{item.quality}" }
+                        td {
+                            onclick: move |event| event.stop_propagation(),
+                            select {
+                                class: "quality-select",
+                                onchange: move |event_data| {
+                                    if let Some(quality) = Quality::from_label(&event_data.value()) {
+                                        let mut pagination = pagination.write();
+                                        let item = &mut pagination.items[index];
+                                        item.select_quality(quality);
+                                        // a selected result is downloaded in the new quality too
+                                        selection.write().update(item);
+                                        filesize::probe_all(std::slice::from_ref(item));
+                                    }
+                                },
+                                for quality in item.video_urls.available() {
+                                    option {
+                                        value: "{quality}",
+                                        selected: item.quality == quality.to_string(),
+                                        "{quality}"
+                                    }
+                                }
+                            }
+                        }
+                        td { class: "file-size", {size_label(filesize::size_of(item))} }
                     }
                 }
             }
@@ -122,12 +440,434 @@ fn media_table(pagination: Signal<Pagination>) -> Element {
     }
 }
 
+// shows a probed file size, or why there is none
+fn size_label(size: Option<FileSize>) -> String {
+    match size {
+        Some(FileSize::Known(bytes)) => utils::format_bytes(bytes),
+        Some(FileSize::Probing) => String::from("…"),
+        Some(FileSize::Unknown) | None => String::from("?"),
+    }
+}
+
+// copies a text to the clipboard of the system through the webview
+fn copy_to_clipboard(text: &str) {
+    let text = serde_json::to_string(text).unwrap_or_default();
+    document::eval(&format!("navigator.clipboard.writeText({text})"));
+}
+
 #[component]
-pub fn search_view() -> Element {
-    let pagination = use_signal(|| Pagination::new());
+fn detail_pane(detail: Signal<Option<SearchItem>>) -> Element {
+    let Some(item) = detail() else {
+        return rsx! {};
+    };
+    let video_urls: Vec<(Quality, String)> = item
+        .video_urls
+        .available()
+        .into_iter()
+        .filter_map(|quality| Some((quality, item.video_urls.get(quality)?.clone())))
+        .collect();
+    let website_url = item.website_url.clone();
 
     rsx! {
-        header_bar { pagination }
+        aside {
+            class: "detail-pane",
+            article {
+                header {
+                    button {
+                        class: "button outline secondary detail-close",
+                        title: "Close",
+                        onclick: move |_| detail.set(None),
+                        "✕"
+                    }
+                    small { "{item.channel} · {item.topic}" }
+                    h4 { "{item.title}" }
+                }
+                p {
+                    class: "detail-description",
+                    match &item.description {
+                        Some(description) => rsx! { "{description}" },
+                        None => rsx! { em { "No description available" } },
+                    }
+                }
+                dl {
+                    dt { "Broadcast" }
+                    dd { "{utils::timestamp_to_german_datetime_precise(item.published)}" }
+                    dt { "Duration" }
+                    dd { "{item.duration}" }
+                    dt { "Size" }
+                    dd { {item.size.map(utils::format_bytes).unwrap_or_else(|| String::from("unknown"))} }
+                    dt { "Selected quality" }
+                    dd { "{item.quality}" }
+                    dt { "ID" }
+                    dd { code { "{item.id}" } }
+                }
+                if !website_url.is_empty() {
+                    button {
+                        class: "button",
+                        onclick: move |_| {
+                            if let Err(error) = webbrowser::open(&website_url) {
+                                error::report(error::AppError::Config(format!(
+                                    "could not open the web browser: {error}"
+                                )));
+                            }
+                        },
+                        "Open website",
+                    }
+                }
+                h6 { "Video urls" }
+                ul {
+                    class: "detail-urls",
+                    for (quality, url) in video_urls {
+                        li {
+                            strong { "{quality}" }
+                            code { "{url}" }
+                            button {
+                                class: "button outline secondary",
+                                title: "Copy url",
+                                onclick: move |_| copy_to_clipboard(&url),
+                                "Copy"
+                            }
+                        }
+                    }
+                    if let Some(url) = item.subtitle_url.clone() {
+                        li {
+                            strong { "CC" }
+                            code { "{url}" }
+                            button {
+                                class: "button outline secondary",
+                                title: "Copy url",
+                                onclick: move |_| copy_to_clipboard(&url),
+                                "Copy"
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[component]
+fn pagination_bar(pagination: Signal<Pagination>, selection: Signal<Selection>) -> Element {
+    // loads another page of the current query
+    let go_to = move |offset: usize| async move {
+        let search = pagination.read().search.clone();
+        search_logic::perform_search(pagination, search, offset).await;
+    };
+
+    let (has_results, current_page, previous_offset, next_offset, last_offset, pages, info) = {
+        let pagination = pagination.read();
+        (
+            pagination.total > 0 && pagination.is_valid(),
+            pagination.current_page(),
+            pagination.previous_offset(),
+            pagination.next_offset(),
+            pagination.last_offset(),
+            pagination
+                .page_numbers(7)
+                .into_iter()
+                .map(|page| (page, pagination.offset_for_page(page)))
+                .collect::<Vec<_>>(),
+            pagination.info(),
+        )
+    };
+
+    if !has_results {
+        return rsx! {
+            nav {
+                ul {
+                    li { "No results" }
+                }
+                ul {
+                    selection_controls { pagination, selection }
+                }
+            }
+        };
+    }
+
+    rsx! {
+        nav {
+            ul {
+                li {
+                    button {
+                        class: "button outline",
+                        disabled: !pagination.read().has_previous_page(),
+                        onclick: move |_| go_to(0),
+                        "«",
+                    }
+                }
+                li {
+                    button {
+                        class: "button outline",
+                        disabled: previous_offset.is_none(),
+                        onclick: move |_| go_to(previous_offset.unwrap_or_default()),
+                        "‹",
+                    }
+                }
+                for (page, offset) in pages {
+                    li {
+                        button {
+                            class: if page == current_page { "button" } else { "button outline" },
+                            onclick: move |_| go_to(offset),
+                            "{page}",
+                        }
+                    }
+                }
+                li {
+                    button {
+                        class: "button outline",
+                        disabled: next_offset.is_none(),
+                        onclick: move |_| go_to(next_offset.unwrap_or_default()),
+                        "›",
+                    }
+                }
+                li {
+                    button {
+                        class: "button outline",
+                        disabled: !pagination.read().has_next_page(),
+                        onclick: move |_| go_to(last_offset),
+                        "»",
+                    }
+                }
+            }
+            ul {
+                li { "{info}" }
+                selection_controls { pagination, selection }
+            }
+        }
+    }
+}
+
+// shows how many results are selected and selects all results of the query
+#[component]
+fn selection_controls(pagination: Signal<Pagination>, selection: Signal<Selection>) -> Element {
+    let selecting = use_signal(|| false);
+    let count = selection.read().len();
+    let total = pagination.read().total.min(search_logic::SELECT_ALL_LIMIT);
+    let (size, _) = filesize::total(selection.read().items().iter().map(filesize::size_of));
+
+    rsx! {
+        li {
+            "{count} selected"
+            if size > 0 {
+                " · {utils::format_bytes(size)}"
+            }
+        }
+        if total > 0 {
+            li {
+                button {
+                    class: "button outline",
+                    disabled: selecting(),
+                    "aria-busy": selecting(),
+                    title: "Select all results of this search (at most {search_logic::SELECT_ALL_LIMIT})",
+                    onclick: move |_| select_all(pagination, selection, selecting),
+                    "Select all {total}",
+                }
+            }
+        }
+        li {
+            button {
+                class: "button outline secondary",
+                disabled: count == 0,
+                onclick: move |_| selection.write().clear(),
+                "Clear",
+            }
+        }
+    }
+}
+
+// selects all results of the current query up to SELECT_ALL_LIMIT
+async fn select_all(
+    pagination: Signal<Pagination>,
+    mut selection: Signal<Selection>,
+    mut selecting: Signal<bool>,
+) {
+    selecting.set(true);
+    let search = pagination.read().search.clone();
+    let (backend, options) = {
+        let settings = &APP_STATE.read().settings;
+        (
+            search_backend::configured(settings),
+            SearchOptions::from_settings(settings),
+        )
+    };
+    let limit = search_logic::SELECT_ALL_LIMIT;
+    match search_logic::fetch_all(&backend, &search, &options, limit).await {
+        Ok(items) => {
+            selection.write().extend(&items);
+            filesize::probe_all(&items);
+        }
+        Err(error) => error::report_with_retry(error, move || {
+            spawn(select_all(pagination, selection, selecting));
+        }),
+    }
+    selecting.set(false);
+}
+
+// lists the selected results to check them before they are queued
+#[component]
+fn selection_review(selection: Signal<Selection>, show_review: Signal<bool>) -> Element {
+    let items = selection.read().items().to_vec();
+    // episodes downloaded or queued before, in any quality, are skipped unless confirmed
+    let duplicates: Vec<Option<Duplicate>> = items.iter().map(download::find_duplicate).collect();
+    let duplicate_count = duplicates.iter().flatten().count();
+    let mut download_again = use_signal(|| false);
+    // results picked from other pages weren't probed yet
+    use_hook(|| filesize::probe_all(selection.read().items()));
+
+    // skipped duplicates don't take any space
+    let (needed, missing) = filesize::total(
+        items
+            .iter()
+            .zip(&duplicates)
+            .filter(|(_, duplicate)| download_again() || duplicate.is_none())
+            .map(|(item, _)| filesize::size_of(item)),
+    );
+    let probing = items
+        .iter()
+        .any(|item| filesize::size_of(item) == Some(FileSize::Probing));
+    let download_dir = APP_STATE.read().settings.download_dir.clone();
+    // the queue fills the same disk before these get their turn
+    let owed = download::owed_bytes(&DOWNLOADS.read(), &download_dir, filesize::known);
+    let space = disk::check_space(&download_dir, needed + owed);
+    let no_space = matches!(space, Err(disk::SpaceError::Insufficient { .. }));
+
+    let download = move |_| {
+        let (directory, template) = {
+            let settings = &APP_STATE.read().settings;
+            (
+                settings.download_dir.clone(),
+                settings.filename_template.clone(),
+            )
+        };
+        let duplicates = if download_again() {
+            Duplicates::Download
+        } else {
+            Duplicates::Skip
+        };
+        let result = download::enqueue(selection.read().items(), &directory, &template, duplicates);
+        match result {
+            Ok(_) => {
+                selection.write().clear();
+                show_review.set(false);
+                APP_STATE.write().view = View::Download;
+            }
+            Err(error) => error::report(error),
+        }
+    };
+
+    rsx! {
+        dialog {
+            open: true,
+            article {
+                class: "selection-review",
+                header {
+                    strong { "Download {items.len()} selected results · {utils::format_bytes(needed)}" }
+                    if owed > 0 {
+                        small { " (plus {utils::format_bytes(owed)} left in the queue)" }
+                    }
+                    if missing > 0 {
+                        small { " (size of {missing} unknown)" }
+                    }
+                }
+                table {
+                    thead {
+                        tr {
+                            th { scope: "col", "channel" }
+                            th { scope: "col", "title" }
+                            th { scope: "col", "topic" }
+                            th { scope: "col", "timestamp" }
+                            th { scope: "col", "quality" }
+                            th { scope: "col", "size" }
+                            th { scope: "col" }
+                            th { scope: "col" }
+                        }
+                    }
+                    tbody {
+                        for (item, duplicate) in items.iter().cloned().zip(duplicates) {
+                            tr {
+                                key: "{item.key()}",
+                                td { "{item.channel}" }
+                                td { "{item.title}" }
+                                td { "{item.topic}" }
+                                td { "{item.timestamp}" }
+                                td { "{item.quality}" }
+                                td { class: "file-size", {size_label(filesize::size_of(&item))} }
+                                td {
+                                    match duplicate {
+                                        Some(Duplicate::Queued) => rsx! {
+                                            small { class: "duplicate-badge", "already queued" }
+                                        },
+                                        Some(Duplicate::Downloaded(path)) => rsx! {
+                                            small {
+                                                class: "duplicate-badge",
+                                                title: "{path.display()}",
+                                                "already downloaded"
+                                            }
+                                        },
+                                        None => rsx! {},
+                                    }
+                                }
+                                td {
+                                    button {
+                                        class: "button outline secondary",
+                                        title: "Remove from selection",
+                                        onclick: move |_| selection.write().remove(item.key()),
+                                        "✕"
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+                if duplicate_count > 0 {
+                    label {
+                        input {
+                            r#type: "checkbox",
+                            checked: download_again(),
+                            oninput: move |e| download_again.set(e.checked()),
+                        }
+                        "Download {duplicate_count} already downloaded or queued results again"
+                    }
+                }
+                match &space {
+                    Err(error) => rsx! { p { class: "space-warning", "{error}" } },
+                    Ok(left) if *left < disk::RESERVE => rsx! {
+                        p {
+                            class: "space-warning",
+                            "Only {utils::format_bytes(*left)} would be left in {download_dir.display()}."
+                        }
+                    },
+                    Ok(_) => rsx! {},
+                }
+                footer {
+                    button {
+                        class: "button secondary",
+                        onclick: move |_| show_review.set(false),
+                        "Cancel",
+                    }
+                    button {
+                        class: "button",
+                        disabled: items.is_empty() || probing || no_space,
+                        "aria-busy": probing,
+                        onclick: download,
+                        "Download",
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[component]
+pub fn search_view(pagination: Signal<Pagination>, selection: Signal<Selection>) -> Element {
+    // the result shown in the detail pane, kept as a copy so that it
+    // stays open while other pages are loaded
+    let detail = use_signal(|| None);
+    let show_review = use_signal(|| false);
+
+    rsx! {
+        header_bar { pagination, selection, show_review }
         main {
             article {
                 padding_bottom: 0,
@@ -135,7 +875,11 @@ pub fn search_view() -> Element {
                 if APP_STATE.read().is_loading {
                     progress {  }
                 } else {
-                    media_table { pagination }
+                    media_table { pagination, selection, detail }
+                }
+                detail_pane { detail }
+                if show_review() {
+                    selection_review { selection, show_review }
                 }
             } // article
         }
@@ -144,14 +888,7 @@ pub fn search_view() -> Element {
             article {
                 padding_bottom: 0,
                 padding_top: 0,
-                nav {
-                    ul {
-                        li { "left" }
-                    }
-                    ul {
-                        li { "right" }
-                    }
-                }
+                pagination_bar { pagination, selection }
             }
         }
     }
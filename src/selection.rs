@@ -0,0 +1,142 @@
+use crate::pagination::SearchItem;
+
+/// Search results picked for download, kept independent of the page shown
+/// so that the selection survives paging and new searches
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Selection {
+    /// selected results in the order they were picked
+    items: Vec<SearchItem>,
+}
+
+impl Selection {
+    pub fn items(&self) -> &[SearchItem] {
+        &self.items
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn contains(&self, item: &SearchItem) -> bool {
+        self.items
+            .iter()
+            .any(|selected| selected.key() == item.key())
+    }
+
+    /// Checks whether every one of the given results is selected
+    pub fn contains_all(&self, items: &[SearchItem]) -> bool {
+        !items.is_empty() && items.iter().all(|item| self.contains(item))
+    }
+
+    /// Selects or deselects a single result
+    pub fn set(&mut self, item: &SearchItem, selected: bool) {
+        if selected {
+            self.extend(std::slice::from_ref(item));
+        } else {
+            self.remove(item.key());
+        }
+    }
+
+    /// Adds results that aren't selected yet
+    pub fn extend(&mut self, items: &[SearchItem]) {
+        for item in items {
+            if !self.contains(item) {
+                self.items.push(item.clone());
+            }
+        }
+    }
+
+    /// Replaces a selected result, e.g. after another quality was picked
+    pub fn update(&mut self, item: &SearchItem) {
+        if let Some(selected) = self
+            .items
+            .iter_mut()
+            .find(|selected| selected.key() == item.key())
+        {
+            *selected = item.clone();
+        }
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        self.items.retain(|item| item.key() != key);
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(id: &str) -> SearchItem {
+        SearchItem {
+            id: String::from(id),
+            title: format!("title {id}"),
+            video_url: format!("https://example.org/{id}.mp4"),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_set_and_remove() {
+        let mut selection = Selection::default();
+        selection.set(&item("a"), true);
+        selection.set(&item("b"), true);
+        selection.set(&item("a"), true);
+        assert_eq!(selection.len(), 2);
+        selection.set(&item("a"), false);
+        assert!(!selection.contains(&item("a")));
+        assert!(selection.contains(&item("b")));
+    }
+
+    #[test]
+    fn test_extend_keeps_order_and_skips_duplicates() {
+        let mut selection = Selection::default();
+        selection.set(&item("b"), true);
+        selection.extend(&[item("a"), item("b"), item("c")]);
+        let ids: Vec<_> = selection
+            .items()
+            .iter()
+            .map(|item| item.id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["b", "a", "c"]);
+    }
+
+    #[test]
+    fn test_contains_all() {
+        let mut selection = Selection::default();
+        assert!(!selection.contains_all(&[]));
+        selection.extend(&[item("a"), item("b")]);
+        assert!(selection.contains_all(&[item("a"), item("b")]));
+        assert!(!selection.contains_all(&[item("a"), item("c")]));
+    }
+
+    #[test]
+    fn test_update_replaces_selected_item() {
+        let mut selection = Selection::default();
+        selection.set(&item("a"), true);
+        let mut changed = item("a");
+        changed.quality = String::from("HD");
+        selection.update(&changed);
+        selection.update(&item("x"));
+        assert_eq!(selection.items(), &[changed]);
+    }
+
+    #[test]
+    fn test_key_falls_back_to_video_url() {
+        let mut selection = Selection::default();
+        let without_id = SearchItem {
+            video_url: String::from("https://example.org/a.mp4"),
+            ..Default::default()
+        };
+        selection.set(&without_id, true);
+        assert!(selection.contains(&without_id));
+        assert!(!selection.contains(&item("")));
+    }
+}
@@ -0,0 +1,289 @@
+use crate::{schedule::DownloadWindow, storage};
+use mediathekviewweb::models::{SortField, SortOrder};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
+const CONFIG_FILE: &str = "config.toml";
+
+#[derive(Debug, thiserror::Error)]
+pub enum SettingsError {
+    #[error("no configuration directory available")]
+    NoConfigDir,
+    #[error("could not access config file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid config file: {0}")]
+    Parse(#[from] toml::de::Error),
+    #[error("could not serialize settings: {0}")]
+    Serialize(#[from] toml::ser::Error),
+    #[error("{}, it was moved to {} and the defaults are used", .1, .0.display())]
+    BackedUp(PathBuf, Box<SettingsError>),
+}
+
+/// Video quality variants offered by the broadcasters
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Quality {
+    HD,
+    SD,
+    LQ,
+}
+
+impl Quality {
+    pub const ALL: [Quality; 3] = [Quality::HD, Quality::SD, Quality::LQ];
+
+    /// Parses the short label as shown in the UI
+    pub fn from_label(label: &str) -> Option<Quality> {
+        Quality::ALL
+            .into_iter()
+            .find(|quality| quality.to_string() == label)
+    }
+}
+
+impl Display for Quality {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Quality::HD => f.write_str("HD"),
+            Quality::SD => f.write_str("SD"),
+            Quality::LQ => f.write_str("LQ"),
+        }
+    }
+}
+
+/// Where searches are answered
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchSource {
+    /// the live MediathekViewWeb API
+    #[default]
+    Api,
+    /// the imported MediathekView film list, works offline
+    Filmliste,
+}
+
+impl SearchSource {
+    pub const ALL: [SearchSource; 2] = [SearchSource::Api, SearchSource::Filmliste];
+
+    /// Parses the label as shown in the UI
+    pub fn from_label(label: &str) -> Option<SearchSource> {
+        SearchSource::ALL
+            .into_iter()
+            .find(|source| source.to_string() == label)
+    }
+}
+
+impl Display for SearchSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SearchSource::Api => f.write_str("MediathekViewWeb"),
+            SearchSource::Filmliste => f.write_str("Offline film list"),
+        }
+    }
+}
+
+/// User settings, persisted in `~/.config/medow/config.toml`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub download_dir: PathBuf,
+    /// qualities in the order they are preferred, the first available one is used
+    pub quality_order: Vec<Quality>,
+    pub page_size: usize,
+    pub max_concurrent_downloads: usize,
+    /// tries per download including the first, only transient failures are retried
+    pub download_attempts: u32,
+    pub filename_template: String,
+    /// channels the search is restricted to, empty for all channels
+    pub channels: Vec<String>,
+    /// number of searches kept in the history, pinned ones not counted
+    pub history_size: usize,
+    /// also fetch the subtitles and store them as `.srt` and `.vtt`
+    pub download_subtitles: bool,
+    /// column the results are sorted by on the server
+    pub sort_field: SortField,
+    pub sort_order: SortOrder,
+    pub search_source: SearchSource,
+    /// film list file imported for the offline search
+    pub filmliste_path: PathBuf,
+    /// minutes between two checks of the subscriptions, 0 turns checking off
+    pub subscription_interval: u64,
+    /// KiB/s shared by all downloads, 0 for no limit
+    pub total_rate_limit: u64,
+    /// KiB/s for each single download, 0 for no limit
+    pub rate_limit_per_download: u64,
+    /// time of day downloads are restricted to
+    pub download_window: DownloadWindow,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            download_dir: crate::download::default_download_dir(),
+            quality_order: vec![Quality::SD, Quality::HD, Quality::LQ],
+            page_size: 15,
+            max_concurrent_downloads: 2,
+            download_attempts: 3,
+            filename_template: String::from(crate::filename::DEFAULT_TEMPLATE),
+            channels: Vec::new(),
+            history_size: 20,
+            download_subtitles: false,
+            sort_field: SortField::Timestamp,
+            sort_order: SortOrder::Descending,
+            search_source: SearchSource::Api,
+            filmliste_path: PathBuf::new(),
+            subscription_interval: 60,
+            total_rate_limit: 0,
+            rate_limit_per_download: 0,
+            download_window: DownloadWindow::default(),
+        }
+    }
+}
+
+impl Settings {
+    /// Loads the settings from the config file, using the defaults
+    /// if there is none yet
+    pub fn load() -> Result<Settings, SettingsError> {
+        let path = config_path()?;
+        match std::fs::read_to_string(&path) {
+            Ok(content) => Ok(toml::from_str::<Settings>(&content)?.clamped()),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(Settings::default()),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    // keeps hand-edited numbers in the ranges the settings form allows
+    fn clamped(mut self) -> Settings {
+        self.page_size = self.page_size.clamp(1, 50);
+        self.max_concurrent_downloads = self.max_concurrent_downloads.clamp(1, 10);
+        self.download_attempts = self.download_attempts.clamp(1, 10);
+        self
+    }
+
+    /// Writes the settings to the config file
+    pub fn save(&self) -> Result<(), SettingsError> {
+        let path = config_path()?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// Moves a config file that can't be parsed aside to `config.toml.bak`
+/// (numbered if taken), so saving the defaults doesn't overwrite it.
+///
+/// Other errors are returned unchanged, the file may be fine once it can be
+/// read again; the caller must not save until then.
+pub fn set_aside(error: SettingsError) -> SettingsError {
+    match config_path() {
+        Ok(path) => set_aside_at(&path, error),
+        Err(_) => error,
+    }
+}
+
+fn set_aside_at(path: &Path, error: SettingsError) -> SettingsError {
+    match &error {
+        SettingsError::Parse(_) => match storage::back_up(path) {
+            Ok(backup) => SettingsError::BackedUp(backup, Box::new(error)),
+            Err(_) => error,
+        },
+        _ => error,
+    }
+}
+
+/// Gets the path of the config file inside the XDG config directory
+pub fn config_path() -> Result<PathBuf, SettingsError> {
+    dirs::config_dir()
+        .map(|dir| dir.join("medow").join(CONFIG_FILE))
+        .ok_or(SettingsError::NoConfigDir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let settings = Settings {
+            download_dir: PathBuf::from("/srv/media"),
+            quality_order: vec![Quality::HD, Quality::SD, Quality::LQ],
+            page_size: 50,
+            max_concurrent_downloads: 4,
+            download_attempts: 5,
+            filename_template: String::from("{topic}/{title}.{ext}"),
+            channels: vec![String::from("ARD"), String::from("ZDF")],
+            history_size: 5,
+            download_subtitles: true,
+            sort_field: SortField::Duration,
+            sort_order: SortOrder::Ascending,
+            search_source: SearchSource::Filmliste,
+            filmliste_path: PathBuf::from("/srv/Filmliste-akt.xz"),
+            subscription_interval: 15,
+            total_rate_limit: 2048,
+            rate_limit_per_download: 512,
+            download_window: DownloadWindow {
+                enabled: true,
+                start: String::from("22:30"),
+                end: String::from("05:00"),
+                timezone: String::from("Europe/Vienna"),
+            },
+        };
+        let content = toml::to_string_pretty(&settings).unwrap();
+        assert_eq!(toml::from_str::<Settings>(&content).unwrap(), settings);
+    }
+
+    #[test]
+    fn test_missing_fields_use_defaults() {
+        let settings: Settings = toml::from_str("page_size = 30").unwrap();
+        assert_eq!(settings.page_size, 30);
+        assert_eq!(
+            settings.quality_order,
+            vec![Quality::SD, Quality::HD, Quality::LQ]
+        );
+        assert_eq!(settings.max_concurrent_downloads, 2);
+        assert!(settings.channels.is_empty());
+    }
+
+    #[test]
+    fn test_out_of_range_numbers_are_clamped() {
+        let content = "page_size = 0\nmax_concurrent_downloads = 0\ndownload_attempts = 99";
+        let settings = toml::from_str::<Settings>(content).unwrap().clamped();
+        assert_eq!(settings.page_size, 1);
+        assert_eq!(settings.max_concurrent_downloads, 1);
+        assert_eq!(settings.download_attempts, 10);
+        assert_eq!(Settings::default().clamped(), Settings::default());
+    }
+
+    #[test]
+    fn test_only_invalid_config_is_set_aside() {
+        let directory = std::env::temp_dir().join(format!("medow-settings-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join(CONFIG_FILE);
+        std::fs::write(&path, "page_size = 30").unwrap();
+
+        // a file that can't be read right now may be fine, it stays
+        let io = std::io::Error::from(std::io::ErrorKind::PermissionDenied);
+        let error = set_aside_at(&path, SettingsError::Io(io));
+        assert!(matches!(error, SettingsError::Io(_)));
+        assert!(path.exists());
+
+        // an earlier backup is kept
+        std::fs::write(directory.join("config.toml.bak"), "page_size = 20").unwrap();
+        let parse = toml::from_str::<Settings>("page_size = [").unwrap_err();
+        let SettingsError::BackedUp(backup, _) = set_aside_at(&path, parse.into()) else {
+            panic!("invalid config wasn't moved aside");
+        };
+        assert_eq!(backup, directory.join("config.toml.bak.1"));
+        assert!(!path.exists());
+        let earlier = std::fs::read_to_string(directory.join("config.toml.bak")).unwrap();
+        assert_eq!(earlier, "page_size = 20");
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_quality_from_label() {
+        assert_eq!(Quality::from_label("HD"), Some(Quality::HD));
+        assert_eq!(Quality::from_label("4K"), None);
+    }
+}
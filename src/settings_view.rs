@@ -0,0 +1,439 @@
+use crate::{
+    bandwidth, download, error,
+    filename::{self, TemplateContext},
+    filmliste::{self, ImportMode},
+    pagination::{SearchItem, VideoUrls},
+    save_settings,
+    settings::{SearchSource, Settings},
+    View, APP_STATE,
+};
+use dioxus::prelude::*;
+use std::path::PathBuf;
+
+#[component]
+fn settings_header() -> Element {
+    rsx! {
+        header {
+            class: "sticky-header",
+            article {
+                padding_bottom: 0,
+                padding_top: 0,
+                nav {
+                    ul {
+                        li { strong { "Settings" } }
+                    }
+                    ul {
+                        li {
+                            button {
+                                class: "button",
+                                onclick: move |_| APP_STATE.write().view = View::Search,
+                                "Search",
+                            }
+                        }
+                        li {
+                            button {
+                                class: "button",
+                                onclick: move |_| APP_STATE.write().view = View::Download,
+                                "Downloads",
+                            }
+                        }
+                        li {
+                            button {
+                                class: "button",
+                                onclick: move |_| APP_STATE.write().view = View::Subscriptions,
+                                "Subscriptions",
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// renders the template for a made up search result to show the user
+// what filenames will look like
+fn template_preview(template: &str) -> Result<String, filename::TemplateError> {
+    let sample = SearchItem {
+        channel: String::from("ARD"),
+        title: String::from("Der Fall Holdt"),
+        topic: String::from("Tatort"),
+        timestamp: String::from("01.02.2024"),
+        duration: String::from("1:29"),
+        quality: String::from("HD"),
+        video_url: String::from("https://example.org/tatort.mp4"),
+        video_urls: VideoUrls {
+            hd: Some(String::from("https://example.org/tatort.mp4")),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let context = TemplateContext {
+        index: 1,
+        item: &sample,
+        ext: "mp4",
+    };
+    filename::render(template, &context).map(|path| path.display().to_string())
+}
+
+// choice of the search source and import of the film list for offline searches
+#[component]
+fn search_source_settings(draft: Signal<Settings>, saved: Signal<bool>) -> Element {
+    let mut status = use_signal(|| None::<String>);
+    let mut importing = use_signal(|| false);
+
+    // parsing the list takes a while, so it runs on a blocking thread
+    let import = move |mode: ImportMode| async move {
+        let path = draft.read().filmliste_path.clone();
+        importing.set(true);
+        status.set(None);
+        let result = tokio::task::spawn_blocking(move || filmliste::import(&path, mode)).await;
+        status.set(Some(match result {
+            Ok(Ok(count)) => format!("{count} films in the offline index."),
+            Ok(Err(error)) => error.to_string(),
+            Err(error) => error.to_string(),
+        }));
+        importing.set(false);
+    };
+
+    rsx! {
+        label {
+            "Search source"
+            select {
+                onchange: move |event_data| {
+                    if let Some(source) = SearchSource::from_label(&event_data.value()) {
+                        draft.write().search_source = source;
+                        saved.set(false);
+                    }
+                },
+                for source in SearchSource::ALL {
+                    option {
+                        value: "{source}",
+                        selected: draft.read().search_source == source,
+                        "{source}"
+                    }
+                }
+            }
+        }
+        fieldset {
+            legend { "Offline film list" }
+            label {
+                "Film list file (Filmliste-akt.xz or Filmliste-diff.xz)"
+                input {
+                    r#type: "text",
+                    placeholder: "/path/to/Filmliste-akt.xz",
+                    value: "{draft.read().filmliste_path.display()}",
+                    oninput: move |event_data| {
+                        draft.write().filmliste_path = PathBuf::from(event_data.value());
+                        saved.set(false);
+                    }
+                }
+            }
+            div {
+                class: "filmliste-import",
+                button {
+                    r#type: "button",
+                    class: "button outline",
+                    disabled: importing() || draft.read().filmliste_path.as_os_str().is_empty(),
+                    "aria-busy": importing(),
+                    onclick: move |_| import(ImportMode::Full),
+                    "Import full list",
+                }
+                button {
+                    r#type: "button",
+                    class: "button outline",
+                    disabled: importing() || draft.read().filmliste_path.as_os_str().is_empty(),
+                    onclick: move |_| import(ImportMode::Diff),
+                    "Add diff list",
+                }
+                if let Some(status) = status() {
+                    small { "{status}" }
+                }
+            }
+        }
+    }
+}
+
+#[component]
+pub fn settings_view() -> Element {
+    // edit a copy so nothing changes before the user saves
+    let mut draft = use_signal(|| APP_STATE.read().settings.clone());
+    let mut saved = use_signal(|| false);
+
+    let preview = template_preview(&draft.read().filename_template);
+    let window_error = draft.read().download_window.validate().err();
+
+    let save = move |_| {
+        let settings: Settings = draft();
+        if let Err(error) = template_preview(&settings.filename_template) {
+            error::report(error);
+            return;
+        }
+        if let Err(error) = settings.download_window.validate() {
+            error::report(error);
+            return;
+        }
+        // applied right away, the error banner offers to retry a failed save
+        bandwidth::apply(&settings);
+        APP_STATE.write().settings = settings;
+        saved.set(save_settings());
+        // more download loops are started if the limit was raised
+        download::start_runner();
+    };
+
+    rsx! {
+        settings_header {}
+        main {
+            article {
+                padding_bottom: 0,
+                padding_top: 5,
+                form {
+                    label {
+                        "Download folder"
+                        input {
+                            r#type: "text",
+                            value: "{draft.read().download_dir.display()}",
+                            oninput: move |event_data| {
+                                draft.write().download_dir = PathBuf::from(event_data.value());
+                                saved.set(false);
+                            }
+                        }
+                    }
+                    fieldset {
+                        legend { "Quality preference (first available is used)" }
+                        for (position, quality) in draft.read().quality_order.iter().copied().enumerate() {
+                            div {
+                                class: "quality-order",
+                                key: "{quality}",
+                                span { "{position + 1}. {quality}" }
+                                button {
+                                    class: "button outline secondary",
+                                    title: "Prefer",
+                                    disabled: position == 0,
+                                    onclick: move |_| {
+                                        draft.write().quality_order.swap(position - 1, position);
+                                        saved.set(false);
+                                    },
+                                    "↑"
+                                }
+                                button {
+                                    class: "button outline secondary",
+                                    title: "Avoid",
+                                    disabled: position + 1 == draft.read().quality_order.len(),
+                                    onclick: move |_| {
+                                        draft.write().quality_order.swap(position, position + 1);
+                                        saved.set(false);
+                                    },
+                                    "↓"
+                                }
+                            }
+                        }
+                    }
+                    label {
+                        "Results per page"
+                        input {
+                            r#type: "number",
+                            min: 1,
+                            max: 50,
+                            value: "{draft.read().page_size}",
+                            oninput: move |event_data| {
+                                if let Ok(page_size) = event_data.value().parse::<usize>() {
+                                    draft.write().page_size = page_size.clamp(1, 50);
+                                    saved.set(false);
+                                }
+                            }
+                        }
+                    }
+                    label {
+                        "Concurrent downloads"
+                        input {
+                            r#type: "number",
+                            min: 1,
+                            max: 10,
+                            value: "{draft.read().max_concurrent_downloads}",
+                            oninput: move |event_data| {
+                                if let Ok(limit) = event_data.value().parse::<usize>() {
+                                    draft.write().max_concurrent_downloads = limit.clamp(1, 10);
+                                    saved.set(false);
+                                }
+                            }
+                        }
+                    }
+                    label {
+                        "Attempts per download (network errors are retried)"
+                        input {
+                            r#type: "number",
+                            min: 1,
+                            max: 10,
+                            value: "{draft.read().download_attempts}",
+                            oninput: move |event_data| {
+                                if let Ok(attempts) = event_data.value().parse::<u32>() {
+                                    draft.write().download_attempts = attempts.clamp(1, 10);
+                                    saved.set(false);
+                                }
+                            }
+                        }
+                    }
+                    div {
+                        class: "grid",
+                        label {
+                            "Total bandwidth (KiB/s, 0 = unlimited)"
+                            input {
+                                r#type: "number",
+                                min: 0,
+                                value: "{draft.read().total_rate_limit}",
+                                oninput: move |event_data| {
+                                    if let Ok(limit) = event_data.value().parse::<u64>() {
+                                        draft.write().total_rate_limit = limit;
+                                        saved.set(false);
+                                    }
+                                }
+                            }
+                        }
+                        label {
+                            "Bandwidth per download (KiB/s, 0 = unlimited)"
+                            input {
+                                r#type: "number",
+                                min: 0,
+                                value: "{draft.read().rate_limit_per_download}",
+                                oninput: move |event_data| {
+                                    if let Ok(limit) = event_data.value().parse::<u64>() {
+                                        draft.write().rate_limit_per_download = limit;
+                                        saved.set(false);
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    fieldset {
+                        label {
+                            input {
+                                r#type: "checkbox",
+                                checked: draft.read().download_window.enabled,
+                                oninput: move |event_data| {
+                                    draft.write().download_window.enabled = event_data.checked();
+                                    saved.set(false);
+                                }
+                            }
+                            "Only download within a time window"
+                        }
+                        div {
+                            class: "grid",
+                            label {
+                                "From"
+                                input {
+                                    r#type: "time",
+                                    disabled: !draft.read().download_window.enabled,
+                                    value: "{draft.read().download_window.start}",
+                                    oninput: move |event_data| {
+                                        draft.write().download_window.start = event_data.value();
+                                        saved.set(false);
+                                    }
+                                }
+                            }
+                            label {
+                                "Until"
+                                input {
+                                    r#type: "time",
+                                    disabled: !draft.read().download_window.enabled,
+                                    value: "{draft.read().download_window.end}",
+                                    oninput: move |event_data| {
+                                        draft.write().download_window.end = event_data.value();
+                                        saved.set(false);
+                                    }
+                                }
+                            }
+                            label {
+                                "Time zone"
+                                input {
+                                    r#type: "text",
+                                    placeholder: "Europe/Berlin",
+                                    disabled: !draft.read().download_window.enabled,
+                                    value: "{draft.read().download_window.timezone}",
+                                    "aria-invalid": window_error.is_some(),
+                                    oninput: move |event_data| {
+                                        draft.write().download_window.timezone = event_data.value();
+                                        saved.set(false);
+                                    }
+                                }
+                            }
+                        }
+                        if let Some(error) = &window_error {
+                            small { "{error}" }
+                        }
+                    }
+                    label {
+                        "Searches kept in history"
+                        input {
+                            r#type: "number",
+                            min: 0,
+                            max: 100,
+                            value: "{draft.read().history_size}",
+                            oninput: move |event_data| {
+                                if let Ok(size) = event_data.value().parse::<usize>() {
+                                    draft.write().history_size = size.min(100);
+                                    saved.set(false);
+                                }
+                            }
+                        }
+                    }
+                    label {
+                        "Check subscriptions every (minutes, 0 = never)"
+                        input {
+                            r#type: "number",
+                            min: 0,
+                            max: 1440,
+                            value: "{draft.read().subscription_interval}",
+                            oninput: move |event_data| {
+                                if let Ok(minutes) = event_data.value().parse::<u64>() {
+                                    draft.write().subscription_interval = minutes.min(1440);
+                                    saved.set(false);
+                                }
+                            }
+                        }
+                    }
+                    label {
+                        "Filename template"
+                        input {
+                            r#type: "text",
+                            value: "{draft.read().filename_template}",
+                            "aria-invalid": preview.is_err(),
+                            oninput: move |event_data| {
+                                draft.write().filename_template = event_data.value();
+                                saved.set(false);
+                            }
+                        }
+                        small {
+                            match &preview {
+                                Ok(example) => rsx! { "Example: {example}" },
+                                Err(error) => rsx! { "{error}" },
+                            }
+                        }
+                    }
+                    search_source_settings { draft, saved }
+                    label {
+                        input {
+                            r#type: "checkbox",
+                            checked: draft.read().download_subtitles,
+                            oninput: move |event_data| {
+                                draft.write().download_subtitles = event_data.checked();
+                                saved.set(false);
+                            }
+                        }
+                        "Download subtitles as .srt and .vtt next to the video"
+                    }
+                    button {
+                        r#type: "button",
+                        class: "button",
+                        disabled: preview.is_err() || window_error.is_some(),
+                        onclick: save,
+                        "Save",
+                    }
+                    if saved() {
+                        small { " Settings saved." }
+                    }
+                }
+            }
+        }
+    }
+}
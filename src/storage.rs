@@ -0,0 +1,182 @@
+use serde::{de::DeserializeOwned, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, thiserror::Error)]
+pub enum StorageError {
+    #[error("no data directory available")]
+    NoDataDir,
+    #[error("could not access {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("invalid data in {0}: {1}")]
+    Json(PathBuf, serde_json::Error),
+    #[error("{}, it was moved to {} and starts over empty", .1, .0.display())]
+    BackedUp(PathBuf, Box<StorageError>),
+}
+
+/// Gets the path of a file inside medow's data directory (`~/.local/share/medow`)
+pub fn data_path(name: &str) -> Result<PathBuf, StorageError> {
+    dirs::data_dir()
+        .map(|dir| dir.join("medow").join(name))
+        .ok_or(StorageError::NoDataDir)
+}
+
+/// Reads a json file from the data directory, returning the default
+/// value if it doesn't exist yet
+pub fn load_json<T: DeserializeOwned + Default>(name: &str) -> Result<T, StorageError> {
+    read_json(&data_path(name)?)
+}
+
+/// Reads a json file, returning the default value if it doesn't exist
+pub fn read_json<T: DeserializeOwned + Default>(path: &Path) -> Result<T, StorageError> {
+    let path = path.to_path_buf();
+    match std::fs::read(&path) {
+        Ok(content) => {
+            serde_json::from_slice(&content).map_err(|error| StorageError::Json(path, error))
+        }
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(T::default()),
+        Err(error) => Err(StorageError::Io(path, error)),
+    }
+}
+
+/// Moves a file that couldn't be parsed aside, so saving afterwards doesn't
+/// overwrite it.
+///
+/// Other errors, like a file that couldn't be read right now, are returned
+/// unchanged; the caller must not save until loading works again.
+pub fn set_aside(error: StorageError) -> StorageError {
+    match &error {
+        StorageError::Json(path, _) => match back_up(path) {
+            Ok(backup) => StorageError::BackedUp(backup, Box::new(error)),
+            Err(_) => error,
+        },
+        _ => error,
+    }
+}
+
+/// Renames a file to `<file>.bak`, or `<file>.bak.1` and so on if that is
+/// taken, so an older backup is never overwritten
+pub fn back_up(path: &Path) -> std::io::Result<PathBuf> {
+    let candidate = |number: u32| {
+        let mut name = path.as_os_str().to_owned();
+        name.push(".bak");
+        if number > 0 {
+            name.push(format!(".{number}"));
+        }
+        PathBuf::from(name)
+    };
+    let mut number = 0;
+    let mut backup = candidate(number);
+    while backup.exists() {
+        number += 1;
+        backup = candidate(number);
+    }
+    std::fs::rename(path, &backup)?;
+    Ok(backup)
+}
+
+/// Reads a file with one json value per line from the data directory.
+///
+/// Lines that can't be parsed, like one cut off by a crash while appending,
+/// are skipped. A missing file gives an empty list.
+pub fn load_json_lines<T: DeserializeOwned>(name: &str) -> Result<Vec<T>, StorageError> {
+    let path = data_path(name)?;
+    match std::fs::read_to_string(&path) {
+        Ok(content) => Ok(parse_json_lines(&content)),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(error) => Err(StorageError::Io(path, error)),
+    }
+}
+
+fn parse_json_lines<T: DeserializeOwned>(content: &str) -> Vec<T> {
+    content
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+/// Appends a value as a single json line to a file in the data directory
+pub fn append_json_line<T: Serialize>(name: &str, value: &T) -> Result<(), StorageError> {
+    let path = data_path(name)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|error| StorageError::Io(parent.to_path_buf(), error))?;
+    }
+    let mut line =
+        serde_json::to_vec(value).map_err(|error| StorageError::Json(path.clone(), error))?;
+    line.push(b'\n');
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| file.write_all(&line))
+        .map_err(|error| StorageError::Io(path, error))
+}
+
+/// Writes a value as json into the data directory, replacing the file atomically
+pub fn save_json<T: Serialize>(name: &str, value: &T) -> Result<(), StorageError> {
+    write_json(name, value, true)
+}
+
+/// Like `save_json`, but without indentation to keep large files small
+pub fn save_json_compact<T: Serialize>(name: &str, value: &T) -> Result<(), StorageError> {
+    write_json(name, value, false)
+}
+
+fn write_json<T: Serialize>(name: &str, value: &T, pretty: bool) -> Result<(), StorageError> {
+    let path = data_path(name)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|error| StorageError::Io(parent.to_path_buf(), error))?;
+    }
+    let temporary = path.with_extension("json.tmp");
+    let file = std::fs::File::create(&temporary)
+        .map_err(|error| StorageError::Io(temporary.clone(), error))?;
+    let mut writer = std::io::BufWriter::new(file);
+    let written = if pretty {
+        serde_json::to_writer_pretty(&mut writer, value)
+    } else {
+        serde_json::to_writer(&mut writer, value)
+    };
+    written.map_err(|error| StorageError::Json(temporary.clone(), error))?;
+    writer
+        .flush()
+        .map_err(|error| StorageError::Io(temporary.clone(), error))?;
+    std::fs::rename(&temporary, &path).map_err(|error| StorageError::Io(path, error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_json_lines_skips_broken_lines() {
+        let content = "[1,2]\n\n[3]\n[4,";
+        let values: Vec<Vec<u32>> = parse_json_lines(content);
+        assert_eq!(values, vec![vec![1, 2], vec![3]]);
+    }
+
+    #[test]
+    fn test_back_up_keeps_older_backups() {
+        let directory = std::env::temp_dir().join(format!("medow-storage-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("queue.json");
+
+        std::fs::write(&path, "first").unwrap();
+        assert_eq!(back_up(&path).unwrap(), directory.join("queue.json.bak"));
+        std::fs::write(&path, "second").unwrap();
+        assert_eq!(back_up(&path).unwrap(), directory.join("queue.json.bak.1"));
+        assert!(!path.exists());
+        let first = std::fs::read_to_string(directory.join("queue.json.bak")).unwrap();
+        assert_eq!(first, "first");
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_only_unparsable_files_are_set_aside() {
+        let path = PathBuf::from("/nonexistent/medow/history.json");
+        let io = std::io::Error::from(std::io::ErrorKind::PermissionDenied);
+        let error = set_aside(StorageError::Io(path, io));
+        assert!(matches!(error, StorageError::Io(..)));
+    }
+}
@@ -0,0 +1,448 @@
+use crate::{
+    disk::{self, SpaceError},
+    download::{self, Duplicates},
+    error::{self, AppError},
+    filesize,
+    pagination::SearchItem,
+    reload_subscriptions,
+    search_backend::{self, BackendError, SearchBackend},
+    search_logic::{self, SearchOptions, SearchRequest},
+    settings::Settings,
+    storage::{self, StorageError},
+    APP_STATE, DOWNLOADS, FILE_SIZES,
+};
+use dioxus::{core::spawn_forever, prelude::*};
+use mediathekviewweb::models::{SortField, SortOrder};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+const SUBSCRIPTIONS_FILE: &str = "subscriptions.json";
+
+/// Number of the newest results looked at on every check
+pub const CHECK_LIMIT: usize = 50;
+
+// set while the subscriptions are checked, so results aren't queued twice
+static CHECKING: AtomicBool = AtomicBool::new(false);
+
+/// A saved search whose new results are downloaded automatically
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Subscription {
+    /// unique name shown in the list
+    pub name: String,
+    pub query: String,
+    /// channel the search is restricted to, empty for all channels
+    pub channel: String,
+    /// minimum duration in minutes
+    pub duration_min: Option<u64>,
+    /// target folder, the download folder from the settings if empty
+    pub destination: PathBuf,
+    /// filename template, the one from the settings if empty
+    pub filename_template: String,
+    pub enabled: bool,
+    /// unix timestamp of the last successful check
+    pub last_checked: Option<i64>,
+    /// keys of the results already downloaded, so every episode is fetched
+    /// once; only those still among the newest results are kept
+    pub downloaded: BTreeSet<String>,
+}
+
+impl Subscription {
+    pub fn search(&self) -> SearchRequest {
+        SearchRequest {
+            query: self.query.clone(),
+            duration_min: self.duration_min,
+            duration_max: None,
+        }
+    }
+
+    /// Gets the options to search with, always looking at the newest results first
+    pub fn options(&self, settings: &Settings) -> SearchOptions {
+        SearchOptions {
+            channels: if self.channel.is_empty() {
+                Vec::new()
+            } else {
+                vec![self.channel.clone()]
+            },
+            sort_field: SortField::Timestamp,
+            sort_order: SortOrder::Descending,
+            quality_order: settings.quality_order.clone(),
+        }
+    }
+
+    pub fn destination<'a>(&'a self, settings: &'a Settings) -> &'a Path {
+        if self.destination.as_os_str().is_empty() {
+            &settings.download_dir
+        } else {
+            &self.destination
+        }
+    }
+
+    pub fn filename_template<'a>(&'a self, settings: &'a Settings) -> &'a str {
+        if self.filename_template.trim().is_empty() {
+            &settings.filename_template
+        } else {
+            &self.filename_template
+        }
+    }
+
+    /// Gets a short description of the search including its filters
+    pub fn label(&self) -> String {
+        let mut label = self.query.trim().to_string();
+        if !self.channel.is_empty() {
+            label.push_str(&format!(" · {}", self.channel));
+        }
+        if let Some(min) = self.duration_min {
+            label.push_str(&format!(" · ≥{min} min"));
+        }
+        label
+    }
+
+    /// Picks the results that haven't been downloaded yet, oldest first
+    pub fn new_items(&self, results: Vec<SearchItem>) -> Vec<SearchItem> {
+        let mut seen = BTreeSet::new();
+        let mut items: Vec<SearchItem> = results
+            .into_iter()
+            .filter(|item| !self.downloaded.contains(item.key()))
+            .filter(|item| seen.insert(item.key().to_string()))
+            .collect();
+        items.reverse();
+        items
+    }
+
+    /// Forgets downloaded results that dropped out of the newest results,
+    /// they can't show up in a check again
+    pub fn forget_unseen(&mut self, seen: &BTreeSet<String>) {
+        self.downloaded.retain(|key| seen.contains(key));
+    }
+
+    pub fn mark_downloaded(&mut self, items: &[SearchItem]) {
+        self.downloaded
+            .extend(items.iter().map(|item| item.key().to_string()));
+    }
+}
+
+/// All subscriptions, stored in the data directory
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Subscriptions {
+    pub subscriptions: Vec<Subscription>,
+}
+
+impl Subscriptions {
+    pub fn load() -> Result<Subscriptions, StorageError> {
+        storage::load_json(SUBSCRIPTIONS_FILE)
+    }
+
+    pub fn save(&self) -> Result<(), StorageError> {
+        storage::save_json(SUBSCRIPTIONS_FILE, self)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Subscription> {
+        self.subscriptions
+            .iter_mut()
+            .find(|subscription| subscription.name == name)
+    }
+
+    /// Adds a subscription, returns false if the name is empty or already taken
+    pub fn add(&mut self, subscription: Subscription) -> bool {
+        let name = subscription.name.trim();
+        if name.is_empty() || self.subscriptions.iter().any(|taken| taken.name == name) {
+            return false;
+        }
+        self.subscriptions.push(Subscription {
+            name: name.to_string(),
+            ..subscription
+        });
+        true
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.subscriptions
+            .retain(|subscription| subscription.name != name);
+    }
+}
+
+/// Outcome of a subscription check
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Check {
+    /// results not downloaded yet, oldest first
+    pub new: Vec<SearchItem>,
+    /// keys of all results looked at
+    pub seen: BTreeSet<String>,
+}
+
+/// Searches for the newest results of a subscription and picks those not
+/// downloaded yet
+pub async fn check(
+    backend: &impl SearchBackend,
+    subscription: &Subscription,
+    options: &SearchOptions,
+) -> Result<Check, BackendError> {
+    let results =
+        search_logic::fetch_all(backend, &subscription.search(), options, CHECK_LIMIT).await?;
+    let seen = results.iter().map(|item| item.key().to_string()).collect();
+    Ok(Check {
+        new: subscription.new_items(results),
+        seen,
+    })
+}
+
+/// Checks all enabled subscriptions and queues their new episodes.
+///
+/// Results count as downloaded once they are queued, a failed download
+/// can be retried in the download list. Episodes downloaded before, e.g.
+/// by hand, are skipped.
+pub async fn check_all() {
+    // nothing to check against while the stored list couldn't be read
+    if APP_STATE.read().subscriptions_locked || CHECKING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    let (backend, settings, subscriptions) = {
+        let state = APP_STATE.read();
+        (
+            search_backend::configured(&state.settings),
+            state.settings.clone(),
+            state.subscriptions.subscriptions.clone(),
+        )
+    };
+
+    // reported once the check is over, so a retry isn't swallowed by it
+    let mut failed = None;
+    for subscription in subscriptions
+        .iter()
+        .filter(|subscription| subscription.enabled)
+    {
+        let options = subscription.options(&settings);
+        let Check { new: items, seen } = match check(&backend, subscription, &options).await {
+            Ok(check) => check,
+            Err(error) => {
+                failed = Some(error);
+                continue;
+            }
+        };
+        let destination = subscription.destination(&settings);
+        let template = subscription.filename_template(&settings);
+        if !items.is_empty() {
+            // a full disk skips the episodes, they are tried again on the next check
+            if let Err(error) = preflight(&items, destination).await {
+                error::report(error);
+                continue;
+            }
+            if let Err(error) = download::enqueue(&items, destination, template, Duplicates::Skip) {
+                error::report(error);
+                continue;
+            }
+        }
+
+        let now = chrono::Utc::now().timestamp();
+        if let Some(stored) = APP_STATE.write().subscriptions.get_mut(&subscription.name) {
+            stored.forget_unseen(&seen);
+            stored.mark_downloaded(&items);
+            stored.last_checked = Some(now);
+        }
+    }
+    CHECKING.store(false, Ordering::SeqCst);
+    save();
+    if let Some(error) = failed {
+        error::report_with_retry(error, || {
+            spawn_forever(check_all());
+        });
+    }
+}
+
+// makes sure the episodes fit into the destination next to what the queue
+// still fetches, files of unknown size and an unknown amount of free space
+// don't hold them back
+async fn preflight(items: &[SearchItem], destination: &Path) -> Result<(), SpaceError> {
+    let Ok(client) = download::http_client() else {
+        return Ok(());
+    };
+    let urls = items.iter().map(|item| item.video_url.clone()).collect();
+    let mut needed = 0;
+    filesize::probe_each(&client, urls, |url, size| {
+        needed += size.unwrap_or_default();
+        FILE_SIZES.write().insert(url, size.into());
+    })
+    .await;
+    // read after probing, downloads may have moved on meanwhile
+    needed += download::owed_bytes(&DOWNLOADS.read(), destination, filesize::known);
+    match disk::check_space(destination, needed) {
+        Err(error @ SpaceError::Insufficient { .. }) => Err(error),
+        _ => Ok(()),
+    }
+}
+
+/// Writes the subscriptions to disk, unless the stored ones couldn't be read
+pub fn save() {
+    if APP_STATE.read().subscriptions_locked {
+        error::report_with_retry(
+            AppError::Filesystem(String::from(
+                "subscriptions are not saved while the stored ones can't be read",
+            )),
+            reload_subscriptions,
+        );
+        return;
+    }
+    let subscriptions = APP_STATE.read().subscriptions.clone();
+    if let Err(error) = subscriptions.save() {
+        error::report_with_retry(error, save);
+    }
+}
+
+/// Starts the loop that checks the subscriptions in the interval from the settings
+pub fn start_polling() {
+    spawn_forever(async {
+        loop {
+            let interval = APP_STATE.read().settings.subscription_interval;
+            if interval > 0 {
+                check_all().await;
+            }
+            // a changed interval is picked up after the current one has passed
+            tokio::time::sleep(Duration::from_secs(interval.max(1) * 60)).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search_backend::SearchResults;
+    use crate::testing::block_on;
+
+    fn item(id: &str) -> SearchItem {
+        SearchItem {
+            id: String::from(id),
+            title: format!("Folge {id}"),
+            ..Default::default()
+        }
+    }
+
+    fn subscription(name: &str) -> Subscription {
+        Subscription {
+            name: String::from(name),
+            query: String::from("#tatort"),
+            enabled: true,
+            ..Default::default()
+        }
+    }
+
+    // answers every search with the same results, newest first
+    struct FixedBackend(Vec<SearchItem>);
+
+    impl SearchBackend for FixedBackend {
+        async fn search(
+            &self,
+            _search: &SearchRequest,
+            _options: &SearchOptions,
+            offset: usize,
+            size: usize,
+        ) -> Result<SearchResults, BackendError> {
+            Ok(SearchResults {
+                total: self.0.len(),
+                items: self.0.iter().skip(offset).take(size).cloned().collect(),
+            })
+        }
+    }
+
+    #[test]
+    fn test_new_items_skips_downloaded_and_starts_with_oldest() {
+        let mut subscription = subscription("Tatort");
+        subscription.mark_downloaded(&[item("2")]);
+        let items = subscription.new_items(vec![item("3"), item("2"), item("1"), item("3")]);
+        let ids: Vec<_> = items.iter().map(|item| item.id.as_str()).collect();
+        assert_eq!(ids, vec!["1", "3"]);
+    }
+
+    #[test]
+    fn test_check_returns_only_new_results() {
+        let backend = FixedBackend(vec![item("b"), item("a")]);
+        let mut subscription = subscription("Tatort");
+        let options = subscription.options(&Settings::default());
+
+        let checked = block_on(check(&backend, &subscription, &options)).unwrap();
+        assert_eq!(checked.new, vec![item("a"), item("b")]);
+        subscription.mark_downloaded(&checked.new);
+        let checked = block_on(check(&backend, &subscription, &options)).unwrap();
+        assert!(checked.new.is_empty());
+        assert_eq!(checked.seen.len(), 2);
+    }
+
+    #[test]
+    fn test_forget_unseen_keeps_newest_results() {
+        let mut subscription = subscription("Tatort");
+        subscription.mark_downloaded(&[item("1"), item("2"), item("3")]);
+        let seen = BTreeSet::from([String::from("2"), String::from("3"), String::from("4")]);
+        subscription.forget_unseen(&seen);
+        assert_eq!(
+            subscription.downloaded,
+            BTreeSet::from([String::from("2"), String::from("3")])
+        );
+    }
+
+    #[test]
+    fn test_options_and_fallbacks() {
+        let settings = Settings::default();
+        let mut subscription = subscription("Tatort");
+        assert!(subscription.options(&settings).channels.is_empty());
+        assert_eq!(subscription.destination(&settings), settings.download_dir);
+        assert_eq!(
+            subscription.filename_template(&settings),
+            settings.filename_template
+        );
+
+        subscription.channel = String::from("ARD");
+        subscription.destination = PathBuf::from("/srv/tatort");
+        subscription.filename_template = String::from("{title}.{ext}");
+        let options = subscription.options(&settings);
+        assert_eq!(options.channels, vec!["ARD"]);
+        assert_eq!(options.sort_field, SortField::Timestamp);
+        assert_eq!(options.sort_order, SortOrder::Descending);
+        assert_eq!(
+            subscription.destination(&settings),
+            Path::new("/srv/tatort")
+        );
+        assert_eq!(subscription.filename_template(&settings), "{title}.{ext}");
+    }
+
+    #[test]
+    fn test_label() {
+        let mut subscription = subscription("Tatort");
+        assert_eq!(subscription.label(), "#tatort");
+        subscription.channel = String::from("ARD");
+        subscription.duration_min = Some(80);
+        assert_eq!(subscription.label(), "#tatort · ARD · ≥80 min");
+    }
+
+    #[test]
+    fn test_add_needs_unique_names() {
+        let mut subscriptions = Subscriptions::default();
+        assert!(subscriptions.add(subscription(" Tatort ")));
+        assert!(!subscriptions.add(subscription("Tatort")));
+        assert!(!subscriptions.add(subscription("  ")));
+        assert!(subscriptions.get_mut("Tatort").is_some());
+        subscriptions.remove("Tatort");
+        assert!(subscriptions.subscriptions.is_empty());
+    }
+
+    #[test]
+    fn test_corrupt_file_is_set_aside() {
+        let directory =
+            std::env::temp_dir().join(format!("medow-subscriptions-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join(SUBSCRIPTIONS_FILE);
+        std::fs::write(&path, r#"{"subscriptions":[{"name":"#).unwrap();
+
+        let error = storage::read_json::<Subscriptions>(&path).unwrap_err();
+        let StorageError::BackedUp(backup, _) = storage::set_aside(error) else {
+            panic!("corrupt subscriptions weren't moved aside");
+        };
+        // saving the empty list now can't touch what the user had
+        assert!(!path.exists());
+        let kept = std::fs::read_to_string(&backup).unwrap();
+        assert_eq!(kept, r#"{"subscriptions":[{"name":"#);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
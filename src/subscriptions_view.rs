@@ -0,0 +1,281 @@
+use crate::{
+    search_logic::CHANNELS,
+    subscriptions::{self, Subscription},
+    utils, View, APP_STATE,
+};
+use dioxus::prelude::*;
+use std::path::PathBuf;
+
+#[component]
+fn subscriptions_header() -> Element {
+    rsx! {
+        header {
+            class: "sticky-header",
+            article {
+                padding_bottom: 0,
+                padding_top: 0,
+                nav {
+                    ul {
+                        li { strong { "Subscriptions" } }
+                    }
+                    ul {
+                        li {
+                            button {
+                                class: "button",
+                                onclick: move |_| APP_STATE.write().view = View::Search,
+                                "Search",
+                            }
+                        }
+                        li {
+                            button {
+                                class: "button",
+                                onclick: move |_| APP_STATE.write().view = View::Download,
+                                "Downloads",
+                            }
+                        }
+                        li {
+                            button {
+                                class: "button",
+                                onclick: move |_| APP_STATE.write().view = View::Settings,
+                                "Settings",
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[component]
+fn subscription_row(subscription: Subscription) -> Element {
+    let settings = APP_STATE.read().settings.clone();
+    let destination = subscription.destination(&settings).display().to_string();
+    let last_checked = match subscription.last_checked {
+        Some(timestamp) => utils::timestamp_to_german_datetime_precise(timestamp),
+        None => String::from("never"),
+    };
+    let name = subscription.name.clone();
+
+    rsx! {
+        tr {
+            td {
+                input {
+                    r#type: "checkbox",
+                    title: "Check this subscription",
+                    checked: subscription.enabled,
+                    oninput: {
+                        let name = name.clone();
+                        move |event: FormEvent| {
+                            if let Some(stored) = APP_STATE.write().subscriptions.get_mut(&name) {
+                                stored.enabled = event.checked();
+                            }
+                            subscriptions::save();
+                        }
+                    },
+                }
+            }
+            td { "{subscription.name}" }
+            td { "{subscription.label()}" }
+            td { "{destination}" }
+            td { "{subscription.downloaded.len()}" }
+            td { "{last_checked}" }
+            td {
+                button {
+                    class: "button outline secondary",
+                    title: "Delete subscription",
+                    onclick: move |_| {
+                        APP_STATE.write().subscriptions.remove(&name);
+                        subscriptions::save();
+                    },
+                    "✕",
+                }
+            }
+        }
+    }
+}
+
+// form to add a subscription, empty folder and template fall back to the settings
+#[component]
+fn subscription_form() -> Element {
+    let mut draft = use_signal(|| Subscription {
+        enabled: true,
+        ..Default::default()
+    });
+    let mut name_taken = use_signal(|| false);
+    let placeholder_template = APP_STATE.read().settings.filename_template.clone();
+    let placeholder_folder = APP_STATE.read().settings.download_dir.display().to_string();
+
+    let add = move |_| {
+        let added = APP_STATE.write().subscriptions.add(draft());
+        name_taken.set(!added);
+        if added {
+            subscriptions::save();
+            draft.set(Subscription {
+                enabled: true,
+                ..Default::default()
+            });
+        }
+    };
+
+    rsx! {
+        form {
+            class: "subscription-form",
+            onsubmit: move |event| event.prevent_default(),
+            div {
+                class: "grid",
+                label {
+                    "Name"
+                    input {
+                        r#type: "text",
+                        placeholder: "Tatort",
+                        value: "{draft.read().name}",
+                        "aria-invalid": name_taken(),
+                        oninput: move |event_data| {
+                            draft.write().name = event_data.value();
+                            name_taken.set(false);
+                        }
+                    }
+                    if name_taken() {
+                        small { "Every subscription needs a new name." }
+                    }
+                }
+                label {
+                    "Search"
+                    input {
+                        r#type: "text",
+                        placeholder: "#tatort",
+                        value: "{draft.read().query}",
+                        oninput: move |event_data| draft.write().query = event_data.value(),
+                    }
+                }
+                label {
+                    "Channel"
+                    select {
+                        onchange: move |event_data| draft.write().channel = event_data.value(),
+                        option { value: "", selected: draft.read().channel.is_empty(), "All channels" }
+                        for channel in CHANNELS {
+                            option {
+                                value: "{channel}",
+                                selected: draft.read().channel == channel,
+                                "{channel}"
+                            }
+                        }
+                    }
+                }
+                label {
+                    "Min. duration (minutes)"
+                    input {
+                        r#type: "number",
+                        min: 0,
+                        value: draft.read().duration_min.map(|min| min.to_string()).unwrap_or_default(),
+                        oninput: move |event_data| {
+                            draft.write().duration_min = event_data.value().parse().ok();
+                        }
+                    }
+                }
+            }
+            div {
+                class: "grid",
+                label {
+                    "Download folder"
+                    input {
+                        r#type: "text",
+                        placeholder: "{placeholder_folder}",
+                        value: "{draft.read().destination.display()}",
+                        oninput: move |event_data| {
+                            draft.write().destination = PathBuf::from(event_data.value());
+                        }
+                    }
+                }
+                label {
+                    "Filename template"
+                    input {
+                        r#type: "text",
+                        placeholder: "{placeholder_template}",
+                        value: "{draft.read().filename_template}",
+                        oninput: move |event_data| {
+                            draft.write().filename_template = event_data.value();
+                        }
+                    }
+                }
+            }
+            button {
+                class: "button",
+                r#type: "submit",
+                disabled: draft.read().name.trim().is_empty() || draft.read().query.trim().is_empty(),
+                onclick: add,
+                "Add subscription",
+            }
+        }
+    }
+}
+
+#[component]
+pub fn subscriptions_view() -> Element {
+    let mut checking = use_signal(|| false);
+    let subscriptions = APP_STATE.read().subscriptions.subscriptions.clone();
+    let is_empty = subscriptions.is_empty();
+    let interval = APP_STATE.read().settings.subscription_interval;
+    let schedule = match interval {
+        0 => String::from("Subscriptions are only checked on demand."),
+        minutes => {
+            format!("Subscriptions are checked every {minutes} minutes while medow is running.")
+        }
+    };
+
+    let check_now = move |_| async move {
+        checking.set(true);
+        subscriptions::check_all().await;
+        checking.set(false);
+    };
+
+    rsx! {
+        subscriptions_header {}
+        main {
+            article {
+                padding_bottom: 0,
+                padding_top: 5,
+                nav {
+                    ul {
+                        li { small { "{schedule}" } }
+                    }
+                    ul {
+                        li {
+                            button {
+                                class: "button outline",
+                                disabled: checking() || is_empty,
+                                "aria-busy": checking(),
+                                onclick: check_now,
+                                "Check now",
+                            }
+                        }
+                    }
+                }
+                if is_empty {
+                    p { "No subscriptions yet. New results of a subscription are downloaded automatically." }
+                } else {
+                    table {
+                        thead {
+                            tr {
+                                th { scope: "col" }
+                                th { scope: "col", "name" }
+                                th { scope: "col", "search" }
+                                th { scope: "col", "folder" }
+                                th { scope: "col", "downloaded" }
+                                th { scope: "col", "last checked" }
+                                th { scope: "col" }
+                            }
+                        }
+                        tbody {
+                            for subscription in subscriptions {
+                                subscription_row { key: "{subscription.name}", subscription }
+                            }
+                        }
+                    }
+                }
+                subscription_form {}
+            }
+        }
+    }
+}
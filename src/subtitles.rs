@@ -0,0 +1,370 @@
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// ARD and others write EBU-TT timecodes relative to a programme start of 10:00:00
+const PROGRAMME_START: Duration = Duration::from_secs(10 * 60 * 60);
+
+#[derive(Debug, thiserror::Error)]
+pub enum SubtitleError {
+    #[error("invalid subtitle xml: {0}")]
+    Xml(#[from] roxmltree::Error),
+    #[error("no subtitles found in the file")]
+    Empty,
+    #[error("http request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("could not write subtitles: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// A single subtitle shown between `start` and `end`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cue {
+    pub start: Duration,
+    pub end: Duration,
+    /// text with one line per row on screen
+    pub text: String,
+}
+
+// timing parameters declared on the root element
+struct Timing {
+    frame_rate: f64,
+    tick_rate: f64,
+}
+
+/// Reads the cues of a TTML / EBU-TT document as used by the broadcasters.
+///
+/// Nested `begin` attributes are added up, `<br/>` starts a new line and
+/// styling is dropped. Timecodes counting from a programme start of
+/// 10:00:00 are moved to the start of the video.
+pub fn parse_ttml(content: &str) -> Result<Vec<Cue>, SubtitleError> {
+    let content = content.trim_start_matches('\u{feff}');
+    let document = roxmltree::Document::parse_with_options(
+        content,
+        roxmltree::ParsingOptions {
+            allow_dtd: true,
+            ..Default::default()
+        },
+    )?;
+    let root = document.root_element();
+    let frame_rate = parameter(root, "frameRate").unwrap_or(30.0)
+        * parameter(root, "frameRateMultiplier").unwrap_or(1.0);
+    let timing = Timing {
+        frame_rate,
+        tick_rate: parameter(root, "tickRate").unwrap_or(1.0),
+    };
+
+    let mut cues = Vec::new();
+    for paragraph in root
+        .descendants()
+        .filter(|node| node.tag_name().name() == "p")
+    {
+        // begin of the surrounding divs, paragraphs are timed relative to them
+        let offset: Duration = paragraph
+            .ancestors()
+            .skip(1)
+            .filter_map(|node| node.attribute("begin"))
+            .filter_map(|value| parse_time(value, &timing))
+            .sum();
+        let Some(start) = paragraph
+            .attribute("begin")
+            .and_then(|value| parse_time(value, &timing))
+        else {
+            continue;
+        };
+        let end = match paragraph.attribute("end") {
+            Some(value) => parse_time(value, &timing),
+            None => paragraph
+                .attribute("dur")
+                .and_then(|value| parse_time(value, &timing))
+                .map(|duration| start + duration),
+        };
+        let Some(end) = end else {
+            continue;
+        };
+
+        let mut text = String::new();
+        collect_text(paragraph, &mut text);
+        let text = text
+            .lines()
+            .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n");
+        // players drop or misorder cues that end before they start
+        if !text.is_empty() && end > start {
+            cues.push(Cue {
+                start: offset + start,
+                end: offset + end,
+                text,
+            });
+        }
+    }
+
+    if cues.is_empty() {
+        return Err(SubtitleError::Empty);
+    }
+    if cues.iter().all(|cue| cue.start >= PROGRAMME_START) {
+        for cue in cues.iter_mut() {
+            cue.start -= PROGRAMME_START;
+            cue.end = cue.end.saturating_sub(PROGRAMME_START);
+        }
+    }
+    cues.sort_by_key(|cue| cue.start);
+    Ok(cues)
+}
+
+// reads a `ttp:` parameter from the root element regardless of its prefix
+fn parameter(root: roxmltree::Node, name: &str) -> Option<f64> {
+    root.attributes()
+        .find(|attribute| attribute.name() == name)
+        .and_then(|attribute| attribute.value().split_whitespace().next()?.parse().ok())
+}
+
+// appends the text of a node, turning `<br/>` into line breaks
+fn collect_text(node: roxmltree::Node, text: &mut String) {
+    for child in node.children() {
+        if child.is_text() {
+            text.push_str(&child.text().unwrap_or_default().replace('\n', " "));
+        } else if child.tag_name().name() == "br" {
+            text.push('\n');
+        } else if child.is_element() {
+            collect_text(child, text);
+        }
+    }
+}
+
+// parses clock times (`01:02:03.400`, `01:02:03:10` with frames)
+// and offset times (`12.5s`, `400ms`, `90t`, `25f`)
+fn parse_time(value: &str, timing: &Timing) -> Option<Duration> {
+    let value = value.trim();
+    if value.contains(':') {
+        let parts: Vec<&str> = value.split(':').collect();
+        let (hours, minutes, seconds, frames) = match parts.as_slice() {
+            [hours, minutes, seconds] => (hours, minutes, seconds, None),
+            [hours, minutes, seconds, frames] => (hours, minutes, seconds, Some(frames)),
+            _ => return None,
+        };
+        let mut total = hours.parse::<f64>().ok()? * 3600.0
+            + minutes.parse::<f64>().ok()? * 60.0
+            + seconds.parse::<f64>().ok()?;
+        if let Some(frames) = frames {
+            total += frames.parse::<f64>().ok()? / timing.frame_rate;
+        }
+        return Duration::try_from_secs_f64(total).ok();
+    }
+
+    let split = value.find(|c: char| c.is_ascii_alphabetic())?;
+    let (number, metric) = value.split_at(split);
+    let number = number.parse::<f64>().ok()?;
+    let seconds = match metric {
+        "h" => number * 3600.0,
+        "m" => number * 60.0,
+        "s" => number,
+        "ms" => number / 1000.0,
+        "f" => number / timing.frame_rate,
+        "t" => number / timing.tick_rate,
+        _ => return None,
+    };
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+// formats a timestamp as `hh:mm:ss` followed by the millisecond separator
+fn format_timestamp(time: Duration, separator: char) -> String {
+    let millis = time.as_millis();
+    format!(
+        "{:02}:{:02}:{:02}{separator}{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+/// Writes the cues as SubRip (`.srt`)
+pub fn to_srt(cues: &[Cue]) -> String {
+    let mut srt = String::new();
+    for (index, cue) in cues.iter().enumerate() {
+        let _ = write!(
+            srt,
+            "{}\n{} --> {}\n{}\n\n",
+            index + 1,
+            format_timestamp(cue.start, ','),
+            format_timestamp(cue.end, ','),
+            cue.text
+        );
+    }
+    srt
+}
+
+/// Writes the cues as WebVTT (`.vtt`)
+pub fn to_vtt(cues: &[Cue]) -> String {
+    let mut vtt = String::from("WEBVTT\n\n");
+    for cue in cues {
+        let _ = write!(
+            vtt,
+            "{} --> {}\n{}\n\n",
+            format_timestamp(cue.start, '.'),
+            format_timestamp(cue.end, '.'),
+            cue.text.replace("-->", "->")
+        );
+    }
+    vtt
+}
+
+/// Downloads the subtitles at `url` and stores them next to the video
+/// as `.srt` and `.vtt`, so players pick them up automatically
+pub async fn download(
+    client: &reqwest::Client,
+    url: &str,
+    video: &Path,
+) -> Result<Vec<PathBuf>, SubtitleError> {
+    let content = client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let cues = parse_ttml(&content)?;
+
+    let srt = video.with_extension("srt");
+    let vtt = video.with_extension("vtt");
+    tokio::fs::write(&srt, to_srt(&cues)).await?;
+    tokio::fs::write(&vtt, to_vtt(&cues)).await?;
+    Ok(vec![srt, vtt])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EBU_TT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<tt:tt xmlns:tt="http://www.w3.org/ns/ttml" xmlns:ttp="http://www.w3.org/ns/ttml#parameter" ttp:timeBase="media" xml:lang="de">
+  <tt:body>
+    <tt:div>
+      <tt:p begin="10:00:01.200" end="10:00:03.000" style="s1">
+        <tt:span style="textWhite">Guten Abend,</tt:span>
+        <tt:br/>
+        <tt:span style="textYellow">meine   Damen und Herren.</tt:span>
+      </tt:p>
+      <tt:p begin="10:01:00.000" end="10:01:02.500"><tt:span>Zweiter Untertitel</tt:span></tt:p>
+    </tt:div>
+  </tt:body>
+</tt:tt>"#;
+
+    fn timing() -> Timing {
+        Timing {
+            frame_rate: 25.0,
+            tick_rate: 10_000_000.0,
+        }
+    }
+
+    fn cue(start: u64, end: u64, text: &str) -> Cue {
+        Cue {
+            start: Duration::from_millis(start),
+            end: Duration::from_millis(end),
+            text: String::from(text),
+        }
+    }
+
+    #[test]
+    fn test_parse_time_clock() {
+        assert_eq!(
+            parse_time("01:02:03.400", &timing()),
+            Some(Duration::from_millis(3_723_400))
+        );
+        assert_eq!(
+            parse_time("00:00:01:05", &timing()),
+            Some(Duration::from_millis(1200))
+        );
+    }
+
+    #[test]
+    fn test_parse_time_offset() {
+        assert_eq!(
+            parse_time("12.5s", &timing()),
+            Some(Duration::from_millis(12_500))
+        );
+        assert_eq!(
+            parse_time("400ms", &timing()),
+            Some(Duration::from_millis(400))
+        );
+        assert_eq!(
+            parse_time("15000000t", &timing()),
+            Some(Duration::from_millis(1500))
+        );
+        assert_eq!(parse_time("abc", &timing()), None);
+        assert_eq!(parse_time("1:2", &timing()), None);
+    }
+
+    #[test]
+    fn test_parse_ebu_tt_with_programme_start() {
+        assert_eq!(
+            parse_ttml(EBU_TT).unwrap(),
+            vec![
+                cue(1200, 3000, "Guten Abend,\nmeine Damen und Herren."),
+                cue(60_000, 62_500, "Zweiter Untertitel"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_drops_cues_ending_before_they_start() {
+        let ttml = r#"<tt xmlns="http://www.w3.org/ns/ttml">
+  <body><div>
+    <p begin="10:00:01.000" end="09:59:59.000">kaputt</p>
+    <p begin="10:00:02.000" end="10:00:02.000">leer</p>
+    <p begin="10:00:03.000" end="10:00:04.500">heil</p>
+  </div></body>
+</tt>"#;
+        assert_eq!(parse_ttml(ttml).unwrap(), vec![cue(3000, 4500, "heil")]);
+
+        let broken = r#"<tt xmlns="http://www.w3.org/ns/ttml">
+  <body><div><p begin="10:00:01.000" end="09:59:59.000">kaputt</p></div></body>
+</tt>"#;
+        assert!(matches!(parse_ttml(broken), Err(SubtitleError::Empty)));
+    }
+
+    #[test]
+    fn test_parse_ticks_and_div_offset() {
+        let ttml = r#"<tt xmlns="http://www.w3.org/ns/ttml" xmlns:ttp="http://www.w3.org/ns/ttml#parameter" ttp:tickRate="10000000">
+  <body><div begin="2s">
+    <p begin="10000000t" dur="5000000t">Hallo</p>
+    <p begin="3s">ohne Ende</p>
+  </div></body>
+</tt>"#;
+        assert_eq!(parse_ttml(ttml).unwrap(), vec![cue(3000, 3500, "Hallo")]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(matches!(parse_ttml("<tt"), Err(SubtitleError::Xml(_))));
+        assert!(matches!(
+            parse_ttml("<tt><body/></tt>"),
+            Err(SubtitleError::Empty)
+        ));
+    }
+
+    #[test]
+    fn test_to_srt() {
+        let cues = vec![
+            cue(1200, 3000, "Zeile 1\nZeile 2"),
+            cue(3_723_400, 3_725_000, "B"),
+        ];
+        assert_eq!(
+            to_srt(&cues),
+            "1\n00:00:01,200 --> 00:00:03,000\nZeile 1\nZeile 2\n\n\
+             2\n01:02:03,400 --> 01:02:05,000\nB\n\n"
+        );
+    }
+
+    #[test]
+    fn test_to_vtt() {
+        let cues = vec![cue(1200, 3000, "A --> B")];
+        assert_eq!(
+            to_vtt(&cues),
+            "WEBVTT\n\n00:00:01.200 --> 00:00:03.000\nA -> B\n\n"
+        );
+    }
+}
@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+
+/// What the test server got asked
+pub struct Request {
+    pub path: String,
+    /// requests to this path so far, including this one
+    pub count: usize,
+}
+
+/// An answer of the test server
+#[derive(Clone)]
+pub struct Response {
+    status: &'static str,
+    headers: Vec<(&'static str, String)>,
+    length: usize,
+    body: Vec<u8>,
+}
+
+impl Response {
+    /// Answers `200 OK` with the given body
+    pub fn ok(body: impl Into<Vec<u8>>) -> Self {
+        let body = body.into();
+        Response {
+            status: "200 OK",
+            headers: Vec::new(),
+            length: body.len(),
+            body,
+        }
+    }
+
+    /// Answers with the given status line and no body
+    pub fn status(status: &'static str) -> Self {
+        Response {
+            status,
+            ..Response::ok(Vec::new())
+        }
+    }
+
+    pub fn with_status(mut self, status: &'static str) -> Self {
+        self.status = status;
+        self
+    }
+
+    pub fn with_header(mut self, name: &'static str, value: impl ToString) -> Self {
+        self.headers.push((name, value.to_string()));
+        self
+    }
+
+    /// Announces a `Content-Length` other than the length of the body, like a
+    /// connection that closes early
+    pub fn with_length(mut self, length: usize) -> Self {
+        self.length = length;
+        self
+    }
+}
+
+/// Serves HTTP on localhost, answering every request with what `respond`
+/// returns for it; gives the base url and the number of requests per path
+pub fn serve(
+    respond: impl Fn(&Request) -> Response + Send + 'static,
+) -> (String, Arc<Mutex<HashMap<String, usize>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let requests: Arc<Mutex<HashMap<String, usize>>> = Arc::default();
+    let counter = requests.clone();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut header = String::new();
+            while reader.read_line(&mut header).unwrap() > 2 {
+                header.clear();
+            }
+            let mut parts = request_line.split_whitespace();
+            let method = parts.next().unwrap_or_default().to_string();
+            let path = parts.next().unwrap_or_default().to_string();
+            let count = {
+                let mut requests = counter.lock().unwrap();
+                let count = requests.entry(path.clone()).or_default();
+                *count += 1;
+                *count
+            };
+            let response = respond(&Request { path, count });
+
+            let mut head = format!(
+                "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+                response.status, response.length
+            );
+            for (name, value) in &response.headers {
+                head.push_str(&format!("{name}: {value}\r\n"));
+            }
+            head.push_str("\r\n");
+            stream.write_all(head.as_bytes()).unwrap();
+            if method != "HEAD" {
+                stream.write_all(&response.body).unwrap();
+            }
+        }
+    });
+    (format!("http://{address}"), requests)
+}
+
+/// Runs a future to completion on a fresh single threaded runtime
+pub fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(future)
+}
@@ -1,5 +1,5 @@
-use std::time::Duration;
 use chrono::{DateTime, Utc};
+use std::time::Duration;
 
 pub fn timestamp_to_german_datetime(timestamp: i64) -> String {
     // Convert timestamp to DateTime<Utc>
@@ -12,6 +12,17 @@ pub fn timestamp_to_german_datetime(timestamp: i64) -> String {
     dt.format("%d.%m.%Y").to_string()
 }
 
+/// Formats a timestamp with date and time as shown in Germany, e.g. `01.02.2024 20:15:00`
+pub fn timestamp_to_german_datetime_precise(timestamp: i64) -> String {
+    match DateTime::from_timestamp(timestamp, 0) {
+        Some(datetime) => datetime
+            .with_timezone(&chrono_tz::Europe::Berlin)
+            .format("%d.%m.%Y %H:%M:%S")
+            .to_string(),
+        None => String::from("unknown"),
+    }
+}
+
 pub fn format_duration(duration: &Option<Duration>) -> String {
     duration
         .map(|d| {
@@ -22,3 +33,37 @@ pub fn format_duration(duration: &Option<Duration>) -> String {
         })
         .unwrap_or_else(|| "unknown".to_string())
 }
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64;
+    let mut unit = "B";
+    for next_unit in UNITS {
+        if value < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        unit = next_unit;
+    }
+    format!("{:.1} {}", value, unit)
+}
+
+pub fn format_speed(bytes_per_second: f64) -> String {
+    format!("{}/s", format_bytes(bytes_per_second as u64))
+}
+
+pub fn format_eta(eta: Option<Duration>) -> String {
+    eta.map(|d| {
+        let total_seconds = d.as_secs();
+        format!(
+            "{:02}:{:02}:{:02}",
+            total_seconds / 3600,
+            (total_seconds % 3600) / 60,
+            total_seconds % 60
+        )
+    })
+    .unwrap_or_else(|| "--:--:--".to_string())
+}